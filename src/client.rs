@@ -0,0 +1,164 @@
+//! Client module for the Vertex AI Setup Tool
+//!
+//! This module provides [`VertexClient`], which resolves the project and region
+//! once and reuses one HTTP client and access token across calls.
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::auth::TokenProvider;
+use crate::pdf::extract_data_with_transport;
+use crate::transport::HttpTransport;
+use crate::vertex_ai::{
+    build_client, count_tokens_with, embed_text_with, explain_not_found, generate_content_raw_with,
+    generate_content_with, validate_base64, ClientConfig, GenerateContentResponse, ModelEndpoint,
+    TokenCount, VertexAIRequest, DEFAULT_EMBEDDING_MODEL, DEFAULT_EXTRACTION_PROMPT,
+};
+
+/// A Vertex AI client bound to one project, region, and model
+///
+/// Unlike the free functions, which resolve the project, fetch a token, and
+/// build an HTTP client on every call, a `VertexClient` does this once: its
+/// connections are pooled and its token is cached until the API rejects it.
+/// Clones share the connection pool and token.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::VertexClient;
+///
+/// # async fn run(pdf_base64: &str) -> anyhow::Result<()> {
+/// let client = VertexClient::new(Some("my-project-id".to_string()), Some("europe-west4"))?
+///     .with_model("gemini-2.0-flash");
+/// let data = client.extract_pdf(pdf_base64, None, None).await?;
+/// let vectors = client.embed_text(&["First chunk".to_string()]).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct VertexClient {
+    endpoint: ModelEndpoint,
+    transport: HttpTransport,
+}
+
+impl VertexClient {
+    /// Creates a client, resolving the project and region once
+    ///
+    /// # Arguments
+    ///
+    /// * `project_id` - Optional project ID (falls back to `VERTEX_AI_PROJECT_ID`)
+    /// * `region` - Optional region (defaults to "us-central1")
+    ///
+    /// # Returns
+    ///
+    /// * `Result<VertexClient, anyhow::Error>` - The client, or an error if the
+    ///   project cannot be resolved or the region is unknown
+    pub fn new(project_id: Option<String>, region: Option<&str>) -> Result<Self> {
+        let endpoint = ModelEndpoint::resolve(project_id, region, None)?;
+        Self::for_endpoint(endpoint, &ClientConfig::from_env())
+    }
+
+    /// Creates a client for a resolved endpoint with custom network settings
+    ///
+    /// The endpoint's impersonation, quota project, and extra headers apply to
+    /// every call.
+    pub fn for_endpoint(endpoint: ModelEndpoint, config: &ClientConfig) -> Result<Self> {
+        let tokens = TokenProvider::gcloud(endpoint.impersonate_service_account.clone());
+        let transport = HttpTransport::for_endpoint(&endpoint, config.timeout)
+            .with_client(build_client(config)?)
+            .with_token_provider(tokens);
+        Ok(Self {
+            endpoint,
+            transport,
+        })
+    }
+
+    /// Sets the model used for generation, extraction, and token counting
+    pub fn with_model(mut self, model_id: impl Into<String>) -> Self {
+        self.endpoint.model_id = model_id.into();
+        self
+    }
+
+    /// Sets whether logged request and response bodies are redacted
+    ///
+    /// Overrides `VERTEX_AI_REDACT_LOGS`; see [`crate::redact`].
+    pub fn with_redaction(mut self, redact: bool) -> Self {
+        self.transport = self.transport.with_redaction(redact);
+        self
+    }
+
+    /// Returns the project, region, and model the client sends requests to
+    pub fn endpoint(&self) -> &ModelEndpoint {
+        &self.endpoint
+    }
+
+    /// Sends a request to the generateContent endpoint and returns the typed response
+    ///
+    /// An expired token is refreshed and the request retried once.
+    pub async fn generate_content(
+        &self,
+        request: &VertexAIRequest,
+    ) -> Result<GenerateContentResponse> {
+        match generate_content_with(&self.transport, request, &self.endpoint).await {
+            Err(e) => Err(explain_not_found(e, &self.transport, &self.endpoint).await),
+            result => result,
+        }
+    }
+
+    /// Sends an arbitrary JSON body to the generateContent endpoint and returns the raw response
+    ///
+    /// See [`crate::vertex_ai::generate_content_raw`].
+    pub async fn generate_content_raw(&self, body: &Value) -> Result<Value> {
+        match generate_content_raw_with(&self.transport, body, &self.endpoint).await {
+            Err(e) => Err(explain_not_found(e, &self.transport, &self.endpoint).await),
+            result => result,
+        }
+    }
+
+    /// Counts the tokens a request would consume without generating any content
+    pub async fn count_tokens(&self, request: &VertexAIRequest) -> Result<TokenCount> {
+        count_tokens_with(&self.transport, request, &self.endpoint).await
+    }
+
+    /// Extracts data from a base64-encoded PDF
+    ///
+    /// # Arguments
+    ///
+    /// * `pdf_base64` - The base64-encoded PDF
+    /// * `prompt` - Optional custom extraction prompt
+    /// * `system_instruction` - Optional custom system instruction
+    ///
+    /// # Returns
+    ///
+    /// * `Result<serde_json::Value, anyhow::Error>` - The extracted data or error
+    pub async fn extract_pdf(
+        &self,
+        pdf_base64: &str,
+        prompt: Option<&str>,
+        system_instruction: Option<&str>,
+    ) -> Result<Value> {
+        validate_base64(pdf_base64)?;
+
+        let request = VertexAIRequest::new_pdf_extraction(
+            pdf_base64,
+            prompt.unwrap_or(DEFAULT_EXTRACTION_PROMPT),
+            system_instruction,
+        );
+        match extract_data_with_transport(&self.transport, &request, &self.endpoint).await {
+            Ok((value, _)) => Ok(value),
+            Err(e) => Err(explain_not_found(e, &self.transport, &self.endpoint).await),
+        }
+    }
+
+    /// Computes text embeddings with `DEFAULT_EMBEDDING_MODEL`
+    ///
+    /// Uses the client's project and region, but not its model. The returned
+    /// vectors are in the same order as `texts`.
+    pub async fn embed_text(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let endpoint = ModelEndpoint {
+            model_id: DEFAULT_EMBEDDING_MODEL.to_string(),
+            ..self.endpoint.clone()
+        };
+        embed_text_with(&self.transport, texts, &endpoint).await
+    }
+}
@@ -2,8 +2,16 @@
 //!
 //! This module provides configuration structures and utilities for the tool.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::gcloud::run_gcloud;
+use crate::setup::{suggest_region, SUPPORTED_REGIONS};
+use crate::vertex_ai::{DEFAULT_EXTRACTION_PROMPT, DEFAULT_SYSTEM_INSTRUCTION};
 
 /// Configuration for the Vertex AI setup tool
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +24,22 @@ pub struct Config {
     pub model: String,
     /// Whether to enable verbose output
     pub verbose: bool,
+    /// Custom extraction prompt (uses `DEFAULT_EXTRACTION_PROMPT` if None)
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// Custom system instruction (uses `DEFAULT_SYSTEM_INSTRUCTION` if None)
+    #[serde(default)]
+    pub system_instruction: Option<String>,
+    /// Service account to impersonate for API calls (e.g., "vertex-runner@proj.iam.gserviceaccount.com")
+    #[serde(default)]
+    pub impersonate: Option<String>,
+    /// Project billed for API quota when it differs from the credentials' project
+    #[serde(default)]
+    pub quota_project: Option<String>,
+    /// https URL of a private endpoint (e.g., Private Service Connect) used
+    /// instead of the public `{region}-aiplatform.googleapis.com` host
+    #[serde(default)]
+    pub endpoint_override: Option<String>,
 }
 
 impl Default for Config {
@@ -25,16 +49,336 @@ impl Default for Config {
             region: "us-central1".to_string(),
             model: "gemini-pro".to_string(),
             verbose: false,
+            prompt: None,
+            system_instruction: None,
+            impersonate: None,
+            quota_project: None,
+            endpoint_override: None,
         }
     }
 }
 
+impl Config {
+    /// Returns the extraction prompt, falling back to the default
+    pub fn extraction_prompt(&self) -> &str {
+        self.prompt.as_deref().unwrap_or(DEFAULT_EXTRACTION_PROMPT)
+    }
+
+    /// Checks that the configuration can be used before any API call is made
+    ///
+    /// Every problem is reported in a single `ConfigError::InvalidConfig`: an
+    /// empty `project_id`, a `region` that is not a known Vertex AI location, a
+    /// blank `model`, or an `endpoint_override` that is not a bare https URL.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use hvertex::Config;
+    ///
+    /// let config = Config::default();
+    /// assert!(config.validate().is_err());
+    ///
+    /// let config = Config {
+    ///     project_id: "my-project-id".to_string(),
+    ///     ..Config::default()
+    /// };
+    /// assert!(config.validate().is_ok());
+    /// ```
+    pub fn validate(&self) -> ConfigResult<()> {
+        let mut problems = Vec::new();
+        if self.project_id.trim().is_empty() {
+            problems.push(format!("project_id is empty (set {})", env::PROJECT_ID));
+        }
+        if !SUPPORTED_REGIONS.contains(&self.region.as_str()) {
+            let suggestion = suggest_region(&self.region, SUPPORTED_REGIONS)
+                .map(|nearest| format!("did you mean '{}'? ", nearest))
+                .unwrap_or_default();
+            problems.push(format!(
+                "unknown region '{}' ({}valid regions are: {})",
+                self.region,
+                suggestion,
+                SUPPORTED_REGIONS.join(", ")
+            ));
+        }
+        if self.model.trim().is_empty() {
+            problems.push("model is empty".to_string());
+        }
+        if let Some(url) = &self.endpoint_override {
+            if let Err(e) = crate::vertex_ai::parse_endpoint_override(url) {
+                problems.push(e.to_string());
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::InvalidConfig(problems.join("; ")).into())
+        }
+    }
+
+    /// Returns the system instruction, falling back to the default
+    pub fn extraction_system_instruction(&self) -> &str {
+        self.system_instruction
+            .as_deref()
+            .unwrap_or(DEFAULT_SYSTEM_INSTRUCTION)
+    }
+}
+
 /// Environment variables used by the tool
 pub mod env {
     /// The Google Cloud project ID environment variable
     pub const PROJECT_ID: &str = "VERTEX_AI_PROJECT_ID";
+    /// The project ID variable set by Cloud Shell and most Google Cloud tooling
+    pub const GOOGLE_CLOUD_PROJECT: &str = "GOOGLE_CLOUD_PROJECT";
+    /// The legacy project ID variable set by older Cloud Functions runtimes
+    pub const GCP_PROJECT: &str = "GCP_PROJECT";
     /// The Google Cloud credentials environment variable
     pub const CREDENTIALS: &str = "GOOGLE_APPLICATION_CREDENTIALS";
+    /// Path to an extra PEM root certificate for HTTPS connections
+    pub const CA_CERT: &str = "VERTEX_AI_CA_CERT";
+    /// Base URL replacing the public Vertex AI host (e.g., a Private Service Connect endpoint)
+    pub const ENDPOINT_OVERRIDE: &str = "VERTEX_AI_ENDPOINT_OVERRIDE";
+    /// Set to "0" or "false" to stop redacting personal data from logged requests and responses
+    pub const REDACT_LOGS: &str = "VERTEX_AI_REDACT_LOGS";
+}
+
+/// Resolves the Google Cloud project ID to use for API calls
+///
+/// Checks, in order: `explicit`, the `VERTEX_AI_PROJECT_ID`,
+/// `GOOGLE_CLOUD_PROJECT`, and `GCP_PROJECT` environment variables, and finally
+/// `gcloud config get-value project`. Empty values are skipped.
+///
+/// # Arguments
+///
+/// * `explicit` - A project ID given by the caller, which always wins
+///
+/// # Returns
+///
+/// * `Result<String, anyhow::Error>` - The project ID, or `ConfigError::InvalidConfig`
+///   listing every place that was checked
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::config::resolve_project_id;
+///
+/// let project_id = resolve_project_id(None)?;
+/// assert_eq!(resolve_project_id(Some("my-project-id"))?, "my-project-id");
+/// Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn resolve_project_id(explicit: Option<&str>) -> Result<String> {
+    resolve_project_id_from(
+        explicit,
+        |name| std::env::var(name).ok(),
+        gcloud_config_project,
+    )
+}
+
+/// Applies the project ID precedence chain to the given sources
+fn resolve_project_id_from(
+    explicit: Option<&str>,
+    lookup_env: impl Fn(&str) -> Option<String>,
+    gcloud_project: impl FnOnce() -> Option<String>,
+) -> Result<String> {
+    let non_empty = |value: Option<String>| value.filter(|value| !value.trim().is_empty());
+
+    if let Some(project_id) = non_empty(explicit.map(str::to_string)) {
+        return Ok(project_id);
+    }
+
+    let env_vars = [env::PROJECT_ID, env::GOOGLE_CLOUD_PROJECT, env::GCP_PROJECT];
+    for name in env_vars {
+        if let Some(project_id) = non_empty(lookup_env(name)) {
+            return Ok(project_id);
+        }
+    }
+
+    if let Some(project_id) = non_empty(gcloud_project()) {
+        return Ok(project_id);
+    }
+
+    Err(ConfigError::InvalidConfig(format!(
+        "No Google Cloud project ID found; checked the explicit project ID, {}, and `gcloud config get-value project`",
+        env_vars.join(", ")
+    ))
+    .into())
+}
+
+/// Returns the project set in the active gcloud configuration, if any
+fn gcloud_config_project() -> Option<String> {
+    let output = run_gcloud(&["config", "get-value", "project"]).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let project_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    // Older gcloud versions print "(unset)" instead of an empty line
+    (project_id != "(unset)").then_some(project_id)
+}
+
+/// Serializes [`ScopedEnv`] guards, since the environment is process-wide
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+/// Sets environment variables for as long as the guard is alive
+///
+/// The previous values are restored (or the variables removed) on drop, so a
+/// test or script can set `VERTEX_AI_PROJECT_ID` without leaking it into the
+/// rest of the process. Guards hold a process-wide lock, so tests using them
+/// never observe each other's variables; set every variable a scope needs with
+/// one guard, because creating a second guard on the same thread deadlocks.
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::config::{env, resolve_project_id, ScopedEnv};
+///
+/// {
+///     let _env = ScopedEnv::set(&[(env::PROJECT_ID, "scoped-project")]);
+///     assert_eq!(resolve_project_id(None)?, "scoped-project");
+/// }
+/// assert_ne!(std::env::var(env::PROJECT_ID).ok().as_deref(), Some("scoped-project"));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[must_use = "the variables are restored as soon as the guard is dropped"]
+pub struct ScopedEnv {
+    previous: Vec<(String, Option<OsString>)>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl ScopedEnv {
+    /// Sets the given variables until the returned guard is dropped
+    pub fn set(vars: &[(&str, &str)]) -> Self {
+        Self::apply(vars.iter().map(|(key, value)| (*key, Some(*value))))
+    }
+
+    /// Removes the given variables until the returned guard is dropped
+    pub fn remove(keys: &[&str]) -> Self {
+        Self::apply(keys.iter().map(|key| (*key, None)))
+    }
+
+    fn apply<'a>(changes: impl Iterator<Item = (&'a str, Option<&'a str>)>) -> Self {
+        // A panicking test poisons the lock, but its guard has already restored the environment
+        let lock = ENV_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let previous = changes
+            .map(|(key, value)| {
+                let old = std::env::var_os(key);
+                match value {
+                    Some(value) => std::env::set_var(key, value),
+                    None => std::env::remove_var(key),
+                }
+                (key.to_string(), old)
+            })
+            .collect();
+        Self {
+            previous,
+            _lock: lock,
+        }
+    }
+}
+
+impl Drop for ScopedEnv {
+    fn drop(&mut self) {
+        // Restore in reverse so a key listed twice ends up with its original value
+        for (key, value) in self.previous.drain(..).rev() {
+            match value {
+                Some(value) => std::env::set_var(&key, value),
+                None => std::env::remove_var(&key),
+            }
+        }
+    }
+}
+
+/// Updates variables in a `.env` file, preserving every other line
+///
+/// Existing assignments of the given keys (with or without an `export` prefix)
+/// are replaced in place, missing keys are appended, and comments and unrelated
+/// variables are left untouched. Values containing spaces or special characters
+/// are double-quoted. The file is created if it does not exist.
+///
+/// # Arguments
+///
+/// * `path` - Path to the `.env` file
+/// * `vars` - The key/value pairs to set
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::config::{env, update_env_file};
+///
+/// update_env_file(".env", &[(env::PROJECT_ID, "my-project-id")])?;
+/// Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn update_env_file(path: impl AsRef<Path>, vars: &[(&str, &str)]) -> Result<()> {
+    let path = path.as_ref();
+    let existing = if path.exists() {
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?
+    } else {
+        String::new()
+    };
+
+    let mut pending: Vec<(&str, &str)> = vars.to_vec();
+    let mut lines: Vec<String> = existing
+        .lines()
+        .map(|line| {
+            let key = env_line_key(line);
+            match pending.iter().position(|(k, _)| Some(*k) == key) {
+                Some(index) => {
+                    let (key, value) = pending.remove(index);
+                    format_env_line(key, value)
+                }
+                None => line.to_string(),
+            }
+        })
+        .collect();
+    lines.extend(
+        pending
+            .into_iter()
+            .map(|(key, value)| format_env_line(key, value)),
+    );
+
+    let mut contents = lines.join("\n");
+    contents.push('\n');
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Persists the project ID and, optionally, the credentials path to a `.env` file
+pub fn save_environment(
+    path: impl AsRef<Path>,
+    project_id: &str,
+    credentials: Option<&str>,
+) -> Result<()> {
+    let mut vars = vec![(env::PROJECT_ID, project_id)];
+    if let Some(credentials) = credentials {
+        vars.push((env::CREDENTIALS, credentials));
+    }
+    update_env_file(path, &vars)
+}
+
+/// Returns the key assigned on a `.env` line, or None for comments and blank lines
+fn env_line_key(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if line.starts_with('#') {
+        return None;
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    let (key, _) = line.split_once('=')?;
+    Some(key.trim())
+}
+
+/// Formats a `KEY=value` line, quoting the value when needed
+fn format_env_line(key: &str, value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || "#\"'\\$=`".contains(c));
+    if needs_quotes {
+        let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("{}=\"{}\"", key, escaped)
+    } else {
+        format!("{}={}", key, value)
+    }
 }
 
 /// Error types for configuration
@@ -50,3 +394,79 @@ pub enum ConfigError {
 
 /// Result type for configuration operations
 pub type ConfigResult<T> = Result<T>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_env_file_merges_and_quotes() {
+        let path = std::env::temp_dir().join("hvertex-test-update-env-file.env");
+        fs::write(
+            &path,
+            "# my settings\nOTHER=keep\nexport VERTEX_AI_PROJECT_ID=old\n",
+        )
+        .unwrap();
+
+        save_environment(&path, "new-project", Some("/path/with space/key.json")).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            contents,
+            "# my settings\nOTHER=keep\nVERTEX_AI_PROJECT_ID=new-project\nGOOGLE_APPLICATION_CREDENTIALS=\"/path/with space/key.json\"\n"
+        );
+    }
+
+    #[test]
+    fn test_scoped_env_restores_previous_values() {
+        const KEY: &str = "HVERTEX_TEST_SCOPED_ENV";
+        {
+            let _env = ScopedEnv::set(&[(KEY, "outer")]);
+            assert_eq!(std::env::var(KEY).unwrap(), "outer");
+        }
+        assert!(std::env::var_os(KEY).is_none());
+
+        std::env::set_var(KEY, "original");
+        {
+            let _env = ScopedEnv::remove(&[KEY]);
+            assert!(std::env::var_os(KEY).is_none());
+        }
+        assert_eq!(std::env::var(KEY).unwrap(), "original");
+        std::env::remove_var(KEY);
+    }
+
+    #[test]
+    fn test_resolve_project_id_precedence() {
+        let env_with = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        let gcloud = || Some("from-gcloud".to_string());
+
+        let vars = &[
+            (env::PROJECT_ID, ""),
+            (env::GOOGLE_CLOUD_PROJECT, "from-env"),
+        ];
+        assert_eq!(
+            resolve_project_id_from(Some("explicit"), env_with(vars), gcloud).unwrap(),
+            "explicit"
+        );
+        assert_eq!(
+            resolve_project_id_from(None, env_with(vars), gcloud).unwrap(),
+            "from-env"
+        );
+        assert_eq!(
+            resolve_project_id_from(None, env_with(&[]), gcloud).unwrap(),
+            "from-gcloud"
+        );
+
+        let error = resolve_project_id_from(None, env_with(&[]), || None)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains(env::GCP_PROJECT), "{}", error);
+    }
+}
@@ -0,0 +1,201 @@
+//! Helpers for running the gcloud CLI
+//!
+//! Every gcloud call in the crate goes through a [`CommandRunner`], which can
+//! be replaced on the current thread with [`set_command_runner`] so that setup,
+//! model listing, and authentication can be tested without gcloud installed.
+
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use std::io;
+use std::process::{Command, Output};
+use std::rc::Rc;
+
+use crate::vertex_ai::VertexError;
+
+/// Runs external programs and collects their output
+pub trait CommandRunner {
+    /// Runs `program` with `args`, waits for it to exit, and returns its output
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<Output>;
+}
+
+/// The default runner, which starts a child process with `std::process::Command`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemCommandRunner;
+
+impl CommandRunner for SystemCommandRunner {
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<Output> {
+        Command::new(program).args(args).output()
+    }
+}
+
+thread_local! {
+    /// The runner used for gcloud calls made on this thread
+    static RUNNER: RefCell<Rc<dyn CommandRunner>> = RefCell::new(Rc::new(SystemCommandRunner));
+}
+
+/// Restores the previous command runner when dropped
+#[must_use = "the runner is restored as soon as the guard is dropped"]
+pub struct CommandRunnerGuard {
+    previous: Option<Rc<dyn CommandRunner>>,
+}
+
+impl Drop for CommandRunnerGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            RUNNER.with(|runner| *runner.borrow_mut() = previous);
+        }
+    }
+}
+
+/// Runs gcloud calls made on the current thread with `runner` until the guard drops
+///
+/// Calls made on other threads, such as tokio's blocking pool, still use
+/// [`SystemCommandRunner`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use hvertex::gcloud::{set_command_runner, MockCommandRunner};
+/// use std::rc::Rc;
+///
+/// let runner = Rc::new(MockCommandRunner::new());
+/// runner.push_output("ya29.token\n");
+/// let _guard = set_command_runner(runner.clone());
+/// assert_eq!(hvertex::get_access_token()?, "ya29.token");
+/// assert_eq!(runner.calls(), ["gcloud auth print-access-token"]);
+/// ```
+pub fn set_command_runner(runner: Rc<dyn CommandRunner>) -> CommandRunnerGuard {
+    let previous = RUNNER.with(|current| current.replace(runner));
+    CommandRunnerGuard {
+        previous: Some(previous),
+    }
+}
+
+/// Runs `gcloud` with the given arguments and returns its output
+///
+/// A missing binary is reported as `VertexError::GcloudNotFound` rather than a
+/// bare I/O error, so users learn how to fix it. A non-zero exit status is not
+/// an error here; callers inspect `output.status` themselves.
+pub(crate) fn run_gcloud(args: &[&str]) -> Result<Output> {
+    let runner = RUNNER.with(|runner| runner.borrow().clone());
+    match runner.run("gcloud", args) {
+        Ok(output) => Ok(output),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(VertexError::GcloudNotFound.into()),
+        Err(e) => Err(VertexError::Io(e))
+            .with_context(|| format!("Failed to execute gcloud {}", args.join(" "))),
+    }
+}
+
+/// A runner that returns canned outputs, for tests that must not run gcloud
+///
+/// Outputs are returned in the order they were pushed, and every command is
+/// recorded so tests can check what would have been run. Available in this
+/// crate's tests and with the `testing` feature.
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Default)]
+pub struct MockCommandRunner {
+    outputs: RefCell<std::collections::VecDeque<io::Result<Output>>>,
+    calls: RefCell<Vec<String>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl MockCommandRunner {
+    /// Creates a runner with no queued outputs
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a successful exit with `stdout`
+    pub fn push_output(&self, stdout: &str) {
+        self.push_exit(0, stdout, "");
+    }
+
+    /// Queues a failed exit (status 1) with `stderr`
+    pub fn push_failure(&self, stderr: &str) {
+        self.push_exit(1, "", stderr);
+    }
+
+    /// Queues an error starting the program, such as `io::ErrorKind::NotFound`
+    pub fn push_error(&self, kind: io::ErrorKind) {
+        self.outputs
+            .borrow_mut()
+            .push_back(Err(io::Error::from(kind)));
+    }
+
+    /// Returns every command run so far, as the program followed by its arguments
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.borrow().clone()
+    }
+
+    fn push_exit(&self, code: i32, stdout: &str, stderr: &str) {
+        self.outputs.borrow_mut().push_back(Ok(Output {
+            status: exit_status(code),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        }));
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl CommandRunner for MockCommandRunner {
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<Output> {
+        let command = std::iter::once(program)
+            .chain(args.iter().copied())
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.calls.borrow_mut().push(command.clone());
+
+        self.outputs.borrow_mut().pop_front().unwrap_or_else(|| {
+            Err(io::Error::other(format!(
+                "MockCommandRunner has no output queued for {}",
+                command
+            )))
+        })
+    }
+}
+
+/// Builds the exit status of a process that exited with `code`
+#[cfg(all(any(test, feature = "testing"), unix))]
+fn exit_status(code: i32) -> std::process::ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    std::process::ExitStatus::from_raw(code << 8)
+}
+
+/// Builds the exit status of a process that exited with `code`
+#[cfg(all(any(test, feature = "testing"), windows))]
+fn exit_status(code: i32) -> std::process::ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    std::process::ExitStatus::from_raw(code as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_runner_replaces_gcloud() {
+        let runner = Rc::new(MockCommandRunner::new());
+        runner.push_output("ok");
+        runner.push_failure("denied");
+        runner.push_error(io::ErrorKind::NotFound);
+        {
+            let _guard = set_command_runner(runner.clone());
+            let output = run_gcloud(&["--version"]).unwrap();
+            assert!(output.status.success());
+            assert_eq!(output.stdout, b"ok");
+
+            let output = run_gcloud(&["auth", "list"]).unwrap();
+            assert_eq!(output.status.code(), Some(1));
+            assert_eq!(output.stderr, b"denied");
+
+            assert!(matches!(
+                run_gcloud(&["info"]).unwrap_err().downcast_ref(),
+                Some(VertexError::GcloudNotFound)
+            ));
+        }
+        assert_eq!(
+            runner.calls(),
+            ["gcloud --version", "gcloud auth list", "gcloud info"]
+        );
+    }
+}
@@ -34,37 +34,92 @@
 //!     region: "us-central1".to_string(),
 //!     model: "gemini-pro".to_string(),
 //!     verbose: false,
+//!     prompt: None,
+//!     system_instruction: None,
+//!     impersonate: None,
+//!     quota_project: None,
+//!     endpoint_override: None,
 //! };
 //! ```
 //!
+//! ## Cargo Features
+//!
+//! - `async` (default): the async API, including the `client`, `pdf`, `queue`,
+//!   `retry`, `stream`, and `transport` modules
+//! - `blocking` (default): the blocking API built on `reqwest::blocking`, such as
+//!   `test_vertex_ai_api_call` and `setup::preflight`
+//! - `testing`: exposes `transport::MockTransport` and `gcloud::MockCommandRunner`
+//!   (implies `async`)
+//!
 //! ## Environment Variables
 //!
 //! The following environment variables are used:
 //!
-//! - `VERTEX_AI_PROJECT_ID`: Your Google Cloud project ID
+//! - `VERTEX_AI_PROJECT_ID`: Your Google Cloud project ID (falls back to
+//!   `GOOGLE_CLOUD_PROJECT`, `GCP_PROJECT`, then the gcloud config)
 //! - `GOOGLE_APPLICATION_CREDENTIALS`: Path to your service account key file
+//! - `HTTPS_PROXY`: Proxy for all API requests (hosts in `NO_PROXY` bypass it)
+//! - `VERTEX_AI_CA_CERT`: Path to an extra PEM root certificate (e.g., a corporate CA)
+//! - `VERTEX_AI_ENDPOINT_OVERRIDE`: https URL of a private endpoint (e.g., Private
+//!   Service Connect) used instead of the public Vertex AI host
+//! - `VERTEX_AI_REDACT_LOGS`: Set to `0` to stop masking inline data, long prompts,
+//!   and emails and phone numbers in debug-logged requests and responses
 //!
 //! ## Error Handling
 //!
 //! The library uses `anyhow::Result` for error handling, providing detailed error messages
-//! and context for debugging.
+//! and context for debugging. Failures from Vertex AI and gcloud carry a typed
+//! `VertexError`, which can be recovered with `downcast_ref`:
+//!
+//! ```rust,no_run
+//! use hvertex::{get_access_token, VertexError};
+//!
+//! if let Err(e) = get_access_token() {
+//!     if let Some(VertexError::Auth(message)) = e.downcast_ref::<VertexError>() {
+//!         eprintln!("Please run `gcloud auth login`: {}", message);
+//!     }
+//! }
+//! ```
 //!
 //! ## License
 //!
 //! This project is licensed under the MIT License.
 
 pub mod auth;
+#[cfg(feature = "async")]
+pub mod client;
+pub mod config;
+pub mod gcloud;
 pub mod models;
+#[cfg(feature = "async")]
 pub mod pdf;
+#[cfg(feature = "async")]
+pub mod queue;
+pub mod redact;
+#[cfg(feature = "async")]
+pub mod retry;
 pub mod setup;
+#[cfg(feature = "async")]
+pub mod stream;
+#[cfg(feature = "async")]
+pub mod transport;
 pub mod vertex_ai;
 
 // Re-export commonly used items
 pub use auth::{get_access_token, setup_authentication};
+#[cfg(feature = "async")]
+pub use client::VertexClient;
+pub use config::{Config, ScopedEnv};
 pub use models::list_vertex_ai_models;
+#[cfg(feature = "async")]
+#[allow(deprecated)]
 pub use pdf::extract_data_from_pdf_v2;
-pub use setup::{ensure_vertex_ai_service, test_vertex_ai_api_call};
-pub use vertex_ai::VertexAIRequest;
+#[cfg(feature = "async")]
+pub use pdf::{extract_data_from_gcs, extract_data_from_pdf_with_options, ExtractionOptions};
+pub use setup::ensure_vertex_ai_service;
+#[cfg(feature = "blocking")]
+pub use setup::test_vertex_ai_api_call;
+pub use vertex_ai::{VertexAIRequest, VertexError};
 
 /// Re-export anyhow::Result for convenience
 pub use anyhow::Result;
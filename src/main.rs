@@ -1,10 +1,3 @@
-mod auth;
-mod models;
-mod pdf;
-mod queue;
-mod setup;
-mod vertex_ai;
-
 /// # Vertex AI Setup Tool
 ///
 /// A powerful command-line tool for setting up and testing Google Cloud Vertex AI integration.
@@ -24,7 +17,8 @@ mod vertex_ai;
 ///
 /// ```bash
 /// cargo install hvertex
-/// hvertex
+/// hvertex setup --project my-project-id
+/// hvertex extract contracts/ --output 'out/{stem}.json'
 /// ```
 ///
 /// ## Configuration
@@ -40,33 +34,220 @@ mod vertex_ai;
 use anyhow::{Context, Result};
 use base64::engine::general_purpose;
 use base64::Engine;
+use clap::Parser;
+use clap::Subcommand;
 use colored::Colorize;
 use futures::StreamExt;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use regex::Regex;
-use serde_json::Value;
 use std::{
     env, fs,
     io::Write,
     path::{Path, PathBuf},
+    process::ExitCode,
     sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use tokio::{sync::Semaphore, time::sleep};
-
-use crate::auth::get_access_token;
-use crate::models::list_vertex_ai_models;
-use crate::pdf::extract_data_from_pdf_v2;
-use crate::queue::{QueueConfig, RequestQueue};
-use crate::setup::{ensure_vertex_ai_service, test_vertex_ai_api_call};
-
-/// Maximum number of retries for rate-limited requests
-const MAX_RETRIES: u32 = 3;
-/// Base delay for exponential backoff (in milliseconds)
-const BASE_DELAY_MS: u64 = 1000;
+
+use hvertex::auth::{get_access_token, setup_authentication};
+use hvertex::config::resolve_project_id;
+use hvertex::models::list_publisher_models;
+use hvertex::pdf::{
+    estimate_token_cost, extract_data_from_pdf_with_options, extract_json_from_raw_text_with,
+    BlockSelection, ExtractionOptions,
+};
+use hvertex::queue::{QueueConfig, RequestQueue};
+use hvertex::setup::{ensure_vertex_ai_service, test_vertex_ai_api_call};
+use hvertex::vertex_ai::{language_directive, DEFAULT_TEXT_MODEL};
+use hvertex::{list_vertex_ai_models, Config};
+use tracing_subscriber::EnvFilter;
+
 /// Maximum concurrent PDF processing tasks
 const MAX_CONCURRENT_TASKS: usize = 3;
 
+/// Placeholder in `--output` replaced by the input file's stem
+const STEM_PLACEHOLDER: &str = "{stem}";
+
+/// Command-line arguments
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Set up Vertex AI and extract structured data from PDFs"
+)]
+struct Cli {
+    /// Google Cloud project ID (defaults to VERTEX_AI_PROJECT_ID,
+    /// GOOGLE_CLOUD_PROJECT, or the gcloud configuration)
+    #[arg(long, global = true)]
+    project: Option<String>,
+
+    /// Vertex AI region
+    #[arg(long, global = true, default_value = "us-central1")]
+    region: String,
+
+    /// Gemini model ID
+    #[arg(long, global = true, default_value = DEFAULT_TEXT_MODEL)]
+    model: String,
+
+    /// Show debug logs from the library (RUST_LOG takes precedence)
+    #[arg(long, short, global = true)]
+    verbose: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// The action to run
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Enable the Vertex AI API, set up credentials, and send a test request
+    Setup,
+    /// List the Gemini models available in the region, with their limits
+    #[command(alias = "list-models")]
+    Models {
+        /// List the models uploaded to the project instead
+        #[arg(long)]
+        custom: bool,
+    },
+    /// Send a test request to the model
+    Test,
+    /// Print an access token for the active gcloud account
+    Token,
+    /// Extract JSON from a PDF, or from every PDF under a directory
+    Extract {
+        /// The PDF file or directory to extract
+        file: PathBuf,
+
+        /// Custom extraction prompt
+        #[arg(long)]
+        prompt: Option<String>,
+
+        /// Where to write the extracted JSON: a directory, or a file path template
+        /// such as `out/{stem}.json` (defaults to an `extracted_data` directory next
+        /// to the input)
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+
+        /// Overwrite output files that already exist
+        #[arg(long)]
+        force: bool,
+
+        /// ISO 639-1 code of the documents' language (e.g., `fr`); keys stay in
+        /// English and values keep the original language
+        #[arg(long)]
+        language: Option<String>,
+    },
+}
+
+/// Settings shared by every PDF in an extraction run
+#[derive(Debug)]
+struct ExtractSettings {
+    /// Base input directory for calculating relative paths
+    input_dir: PathBuf,
+    /// Where to save the JSON files
+    output: OutputTarget,
+    /// Whether to overwrite existing JSON files
+    force: bool,
+    /// Directory for the extraction logs
+    log_dir: PathBuf,
+    /// The project, region, and model to extract with
+    config: Config,
+    /// ISO 639-1 code of the documents' language
+    language: Option<String>,
+}
+
+/// Where extracted JSON files are written
+#[derive(Debug, Clone)]
+enum OutputTarget {
+    /// A directory mirroring the input directory layout, one `{stem}.json` per PDF
+    Directory(PathBuf),
+    /// A path template containing `{stem}`, or a single literal file path
+    Template(String),
+}
+
+impl OutputTarget {
+    /// Interprets an `--output` argument
+    ///
+    /// Paths containing `{stem}` are templates. Otherwise, existing directories,
+    /// paths ending in a separator, and paths without an extension are treated
+    /// as directories; anything else is a literal file path.
+    ///
+    /// A literal file path is rejected when the input is a directory, since
+    /// every PDF would be written to the same file.
+    fn parse(output: &Path, input_is_directory: bool) -> Result<Self> {
+        let text = output.to_string_lossy();
+        if text.contains(STEM_PLACEHOLDER) {
+            return Ok(Self::Template(text.into_owned()));
+        }
+
+        let is_directory = output.is_dir()
+            || text.ends_with(std::path::MAIN_SEPARATOR)
+            || text.ends_with('/')
+            || output.extension().is_none();
+        if is_directory {
+            Ok(Self::Directory(output.to_path_buf()))
+        } else if input_is_directory {
+            anyhow::bail!(
+                "--output {} is a single file but the input is a directory; \
+                 use a directory or a template containing {}",
+                text,
+                STEM_PLACEHOLDER
+            )
+        } else {
+            Ok(Self::Template(text.into_owned()))
+        }
+    }
+
+    /// Returns the output file for a PDF
+    ///
+    /// # Arguments
+    ///
+    /// * `pdf_path` - The input PDF
+    /// * `relative_dir` - The PDF's directory relative to the input directory
+    fn path_for(&self, pdf_path: &Path, relative_dir: &Path) -> PathBuf {
+        let stem = pdf_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match self {
+            Self::Directory(dir) => dir.join(relative_dir).join(format!("{}.json", stem)),
+            Self::Template(template) => PathBuf::from(template.replace(STEM_PLACEHOLDER, &stem)),
+        }
+    }
+}
+
+/// Writes an output file, creating its parent directories
+///
+/// Refuses to replace an existing file unless `force` is set.
+fn write_output(path: &Path, contents: &str, force: bool) -> Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    let mut file = options.open(path).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::AlreadyExists {
+            anyhow::anyhow!(
+                "{} already exists (pass --force to overwrite it)",
+                path.display()
+            )
+        } else {
+            anyhow::Error::new(e).context(format!("Failed to create {}", path.display()))
+        }
+    })?;
+    file.write_all(contents.as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
 /// Struct to hold logging information
 #[derive(Debug)]
 struct ExtractionLog {
@@ -132,9 +313,7 @@ fn write_log_entry(log_dir: &Path, log: ExtractionLog) -> Result<()> {
 /// # Arguments
 ///
 /// * `path` - Path to the PDF file
-/// * `input_dir` - Base input directory for calculating relative paths
-/// * `output_base_dir` - Base output directory for saving JSON files
-/// * `log_dir` - Base log directory for saving extraction logs
+/// * `settings` - Input, output, and model settings for the run
 /// * `request_queue` - Request queue for rate limiting
 /// * `progress_bar` - Progress bar for tracking progress
 ///
@@ -143,9 +322,7 @@ fn write_log_entry(log_dir: &Path, log: ExtractionLog) -> Result<()> {
 /// * `Result<(), anyhow::Error>` - Success or error status
 async fn process_single_pdf(
     path: PathBuf,
-    input_dir: &Path,
-    output_base_dir: &Path,
-    log_dir: &Path,
+    settings: &ExtractSettings,
     request_queue: &RequestQueue,
     progress_bar: ProgressBar,
 ) -> Result<()> {
@@ -162,28 +339,34 @@ async fn process_single_pdf(
 
     // Read and encode the PDF file
     let pdf_bytes = fs::read(&path)?;
+    let estimated_tokens = estimate_token_cost(pdf_bytes.len());
     let pdf_base64 = general_purpose::STANDARD.encode(pdf_bytes);
 
-    // Create the output directory structure
-    let relative_path = path.parent().unwrap().strip_prefix(input_dir)?;
-    let output_dir = output_base_dir.join(relative_path);
-    fs::create_dir_all(&output_dir)?;
-
-    // Generate output filename
-    let output_filename = path.file_stem().unwrap().to_string_lossy().to_string() + ".json";
-    let output_path = output_dir.join(output_filename);
+    // Resolve the output file, mirroring the input layout for directory outputs
+    let relative_path = path
+        .parent()
+        .and_then(|parent| parent.strip_prefix(&settings.input_dir).ok())
+        .unwrap_or(Path::new(""));
+    let output_path = settings.output.path_for(&path, relative_path);
+    let log_dir = settings.log_dir.as_path();
 
     // Clone values for the closure
     let pdf_base64 = pdf_base64.clone();
     let path_display = path.display().to_string();
+    let mut options = ExtractionOptions::default()
+        .project_id(settings.config.project_id.clone())
+        .region(settings.config.region.clone())
+        .model(settings.config.model.clone());
+    options.prompt = settings.config.prompt.clone();
+    options.language = settings.language.clone();
 
     // Execute the request through the queue
     match request_queue
-        .execute(move || {
+        .execute_with_cost(estimated_tokens, move || {
             // This closure will be retried automatically by the queue system
             tokio::task::block_in_place(|| {
                 tokio::runtime::Handle::current().block_on(async {
-                    extract_data_from_pdf_v2(&pdf_base64, None, None, None, None, None).await
+                    extract_data_from_pdf_with_options(&pdf_base64, &options).await
                 })
             })
         })
@@ -193,7 +376,7 @@ async fn process_single_pdf(
             // Process the response
             let json_data =
                 if let Some(raw_text) = api_response.get("raw_text").and_then(|v| v.as_str()) {
-                    match extract_json_from_raw_text(raw_text) {
+                    match extract_json_from_raw_text_with(raw_text, BlockSelection::Largest, true) {
                         Ok(extracted_json) => extracted_json,
                         Err(e) => {
                             progress_bar.set_message(format!(
@@ -210,7 +393,7 @@ async fn process_single_pdf(
 
             // Write the JSON to file
             let json_str = serde_json::to_string_pretty(&json_data)?;
-            fs::write(&output_path, json_str)?;
+            write_output(&output_path, &json_str, settings.force)?;
 
             // Log successful extraction
             let log = ExtractionLog::new(path_display.clone(), "SUCCESS".to_string(), None);
@@ -240,15 +423,19 @@ async fn process_single_pdf(
 ///
 /// # Arguments
 ///
-/// * `dir` - Directory to scan for PDF files
+/// * `dir` - Directory to scan for PDF files, or a single PDF file
 ///
 /// # Returns
 ///
 /// * `Result<Vec<PathBuf>, anyhow::Error>` - List of PDF file paths
 fn collect_pdf_files(dir: &Path) -> Result<Vec<PathBuf>> {
-    let mut pdf_files = Vec::new();
+    if dir.is_file() {
+        return Ok(vec![dir.to_path_buf()]);
+    }
 
-    for entry in fs::read_dir(dir)? {
+    let mut pdf_files = Vec::new();
+    let entries = fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+    for entry in entries {
         let entry = entry?;
         let path = entry.path();
 
@@ -268,24 +455,21 @@ fn collect_pdf_files(dir: &Path) -> Result<Vec<PathBuf>> {
 ///
 /// # Arguments
 ///
-/// * `input_dir` - The input directory containing PDF files
-/// * `output_base_dir` - The base directory where extracted JSON files will be saved
-/// * `log_dir` - The base directory where extraction logs will be saved
+/// * `pdf` - A PDF file, or a directory containing PDF files
+/// * `settings` - Input, output, and model settings for the run
 ///
 /// # Returns
 ///
-/// * `Result<(), anyhow::Error>` - Success or error status
-async fn process_pdfs_recursively(
-    input_dir: &Path,
-    output_base_dir: &Path,
-    log_dir: &Path,
-) -> Result<()> {
-    // Create the output and log directories if they don't exist
-    fs::create_dir_all(output_base_dir)?;
-    fs::create_dir_all(log_dir)?;
+/// * `Result<(), anyhow::Error>` - Success, or an error counting the files that
+///   failed once every file has been attempted
+async fn process_pdfs_recursively(pdf: &Path, settings: ExtractSettings) -> Result<()> {
+    // Create the log directory if it doesn't exist; output directories are
+    // created as files are written
+    fs::create_dir_all(&settings.log_dir)
+        .with_context(|| format!("Failed to create {}", settings.log_dir.display()))?;
 
     // Collect all PDF files first
-    let pdf_files = collect_pdf_files(input_dir)?;
+    let pdf_files = collect_pdf_files(pdf)?;
     let total_files = pdf_files.len();
     println!("\nFound {} PDF files to process", total_files);
 
@@ -295,6 +479,7 @@ async fn process_pdfs_recursively(
         refill_tokens: 100000,                    // Refill 100k tokens per interval
         refill_interval: Duration::from_secs(60), // Refill every minute
         max_concurrent_requests: MAX_CONCURRENT_TASKS,
+        ..QueueConfig::default()
     };
     let request_queue = Arc::new(RequestQueue::new(queue_config));
 
@@ -310,29 +495,25 @@ async fn process_pdfs_recursively(
     let multi_progress = Arc::new(MultiProgress::new());
 
     // Process files in parallel with controlled concurrency
-    let mut tasks = futures::stream::iter(pdf_files.into_iter().map(|pdf_path| {
+    let settings = Arc::new(settings);
+    let tasks = futures::stream::iter(pdf_files.into_iter().map(|pdf_path| {
         let request_queue = Arc::clone(&request_queue);
-        let input_dir = Arc::new(input_dir.to_path_buf());
-        let output_base_dir = Arc::new(output_base_dir.to_path_buf());
-        let log_dir = Arc::new(log_dir.to_path_buf());
+        let settings = Arc::clone(&settings);
         let multi_progress = Arc::clone(&multi_progress);
 
         async move {
             // Create a new progress bar for this file
             let progress_bar = multi_progress.add(ProgressBar::new(1));
 
-            let result = process_single_pdf(
-                pdf_path.clone(),
-                &input_dir,
-                &output_base_dir,
-                &log_dir,
-                &request_queue,
-                progress_bar,
-            )
-            .await;
+            let result =
+                process_single_pdf(pdf_path.clone(), &settings, &request_queue, progress_bar).await;
 
-            if let Err(e) = result {
-                eprintln!("Error processing {}: {}", pdf_path.display(), e);
+            match result {
+                Ok(()) => true,
+                Err(e) => {
+                    eprintln!("Error processing {}: {}", pdf_path.display(), e);
+                    false
+                }
             }
         }
     }))
@@ -340,100 +521,184 @@ async fn process_pdfs_recursively(
     .collect::<Vec<_>>();
 
     // Wait for all tasks to complete
-    tasks.await;
+    let failed = tasks
+        .await
+        .into_iter()
+        .filter(|succeeded| !succeeded)
+        .count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} files failed", failed, total_files);
+    }
 
     Ok(())
 }
 
 /// Main entry point for the application
+///
+/// Errors are printed as a short message chain and turned into a non-zero exit
+/// code instead of a panic or a `Debug` dump.
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Print a welcome message with styling
-    println!("{}", "Vertex AI PDF Data Extraction Tool".green().bold());
-    println!("{}", "================================".green());
-    println!();
-
-    // Load environment variables
-    load_environment()?;
-
-    // Check environment variables
-    check_environment_variables()?;
+async fn main() -> ExitCode {
+    // Prints usage and exits with code 2 on invalid arguments
+    let cli = Cli::parse();
 
-    // Define input, output, and log directories
-    let input_dir = PathBuf::from("/Users/hamzeghalebi/project/remolab/giva/pdfproject/data");
-    let output_dir = input_dir.parent().unwrap().join("extracted_data");
-    let log_dir = input_dir.parent().unwrap().join("logs");
-
-    println!("\n{}", "Starting PDF processing...".blue().bold());
-    println!(
-        "Input directory: {}",
-        input_dir.display().to_string().cyan()
-    );
-    println!(
-        "Output directory: {}",
-        output_dir.display().to_string().cyan()
-    );
-    println!("Log directory: {}", log_dir.display().to_string().cyan());
-
-    // Process all PDFs recursively and asynchronously
-    process_pdfs_recursively(&input_dir, &output_dir, &log_dir).await?;
-
-    println!("\n{}", "Processing complete!".green().bold());
-    Ok(())
-}
-
-/// Extracts and parses JSON data from raw text that contains Markdown code blocks
-///
-/// This function is designed to handle responses from the Vertex AI API that
-/// may return JSON data wrapped in Markdown code blocks (```json ... ```).
-/// It will extract the JSON content and parse it into a proper serde_json::Value.
-///
-/// # Arguments
-///
-/// * `raw_text` - A string slice containing the raw text with potential JSON data in code blocks
-///
-/// # Returns
-///
-/// * `Result<serde_json::Value, anyhow::Error>` - The parsed JSON value or an error
-///
-/// # Example
-///
-/// ```rust
-/// let response = extract_data_from_pdf(&pdf_base64, None, None, None)?;
-///
-/// // If the response contains raw_text with JSON in backticks
-/// if let Some(raw_text) = response.get("raw_text") {
-///     if let Some(text) = raw_text.as_str() {
-///         let parsed_json = extract_json_from_raw_text(text)?;
-///         println!("{}", serde_json::to_string_pretty(&parsed_json)?);
-///     }
-/// }
-/// ```
-pub fn extract_json_from_raw_text(raw_text: &str) -> Result<Value> {
-    // First, check if the input is a JSON object with a "raw_text" field
-    if let Ok(parsed) = serde_json::from_str::<Value>(raw_text) {
-        if let Some(inner_text) = parsed.get("raw_text").and_then(|v| v.as_str()) {
-            // If we have a "raw_text" field, use its value as our raw text
-            return extract_json_from_raw_text(inner_text);
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{} {:#}", "Error:".red().bold(), e);
+            ExitCode::FAILURE
         }
     }
+}
 
-    // Create a regex to match JSON content within triple backticks
-    // The (?s) modifier enables "dot matches newline" mode
-    let re = Regex::new(r"```(?:json)?\s*([\s\S]*?)\s*```").context("Failed to compile regex")?;
+/// Runs the selected subcommand
+async fn run(cli: Cli) -> Result<()> {
+    // Route library logs to stderr, honouring RUST_LOG (defaults to warn, or
+    // debug with --verbose)
+    let default_level = if cli.verbose { "debug" } else { "warn" };
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level)),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let Cli {
+        project,
+        region,
+        model,
+        verbose,
+        command,
+    } = cli;
+
+    // Prints the banner and loads the configuration every command but `token` needs
+    let start = move || -> Result<Config> {
+        // Print a welcome message with styling
+        println!("{}", "Vertex AI Setup Tool".green().bold());
+        println!("{}", "====================".green());
+        println!();
+
+        // Load environment variables
+        load_environment()?;
+
+        // Fail fast on misconfiguration before touching gcloud or the API
+        let config = Config {
+            project_id: resolve_project_id(project.as_deref())?,
+            region,
+            model,
+            verbose,
+            ..Config::default()
+        };
+        config.validate()?;
+        Ok(config)
+    };
 
-    // Try to find a match
-    if let Some(captures) = re.captures(raw_text) {
-        if let Some(json_str) = captures.get(1) {
-            // Parse the extracted JSON string
-            return serde_json::from_str::<Value>(json_str.as_str())
-                .context("Failed to parse extracted JSON");
+    match command {
+        // Print only the token, so the output can be captured by a shell script
+        Command::Token => {
+            // Load environment variables silently
+            let _ = dotenv::dotenv();
+            println!("{}", get_access_token()?);
+        }
+        Command::Setup => {
+            let config = start()?;
+            ensure_vertex_ai_service(&config.project_id)?;
+            println!("✅ Vertex AI API is enabled");
+            setup_authentication(&config.project_id)?;
+            println!("✅ Application Default Credentials are set up");
+            run_test_call(&config).await?;
+        }
+        Command::Models { custom: true } => {
+            let config = start()?;
+            let models = list_vertex_ai_models(&config.project_id, &config.region)?;
+            if models.is_empty() {
+                println!("No models found in {}", config.region);
+            }
+            for model in models {
+                println!("{}", model);
+            }
+        }
+        Command::Models { custom: false } => {
+            let config = start()?;
+            let models = list_publisher_models(&config.project_id, &config.region).await?;
+            let limit =
+                |limit: Option<u32>| limit.map_or("-".to_string(), |limit| limit.to_string());
+            for model in models.iter().filter(|model| model.name.contains("gemini")) {
+                println!(
+                    "{}  {}  input: {}  output: {}",
+                    model.name.rsplit('/').next().unwrap_or(&model.name).cyan(),
+                    model.display_name,
+                    limit(model.input_token_limit),
+                    limit(model.output_token_limit)
+                );
+            }
+        }
+        Command::Test => {
+            let config = start()?;
+            check_environment_variables()?;
+            run_test_call(&config).await?;
+        }
+        Command::Extract {
+            file: pdf,
+            prompt,
+            output,
+            force,
+            language,
+        } => {
+            let config = start()?;
+            if !pdf.exists() {
+                anyhow::bail!("{} does not exist", pdf.display());
+            }
+            // Reject an unsupported language before any file is sent
+            if let Some(language) = &language {
+                language_directive(language)?;
+            }
+
+            // Outputs and logs go next to the input file, or next to the input
+            // directory, unless told otherwise
+            let (input_dir, base_dir) = if pdf.is_file() {
+                let parent = pdf.parent().unwrap_or(Path::new("")).to_path_buf();
+                (parent.clone(), parent)
+            } else {
+                let parent = pdf.parent().unwrap_or(Path::new("")).to_path_buf();
+                (pdf.clone(), parent)
+            };
+            let output_dir = output.unwrap_or_else(|| base_dir.join("extracted_data"));
+            let log_dir = base_dir.join("logs");
+            let output_target = OutputTarget::parse(&output_dir, pdf.is_dir())?;
+
+            println!("\n{}", "Starting PDF processing...".blue().bold());
+            println!("Input: {}", pdf.display().to_string().cyan());
+            println!("Output: {}", output_dir.display().to_string().cyan());
+            println!("Log directory: {}", log_dir.display().to_string().cyan());
+
+            let settings = ExtractSettings {
+                input_dir,
+                output: output_target,
+                force,
+                log_dir,
+                config: Config { prompt, ..config },
+                language,
+            };
+            process_pdfs_recursively(&pdf, settings).await?;
+
+            println!("\n{}", "Processing complete!".green().bold());
         }
     }
+    Ok(())
+}
 
-    // If no code blocks were found, try to parse the entire text as JSON
-    serde_json::from_str::<Value>(raw_text)
-        .context("Failed to parse text as JSON and no code blocks were found")
+/// Sends a test request, off the async runtime since the call is blocking
+async fn run_test_call(config: &Config) -> Result<()> {
+    let project_id = config.project_id.clone();
+    let region = config.region.clone();
+    let model = config.model.clone();
+    tokio::task::spawn_blocking(move || {
+        test_vertex_ai_api_call(&project_id, Some(&region), &model)
+    })
+    .await??;
+    println!("✅ Test request to {} succeeded", config.model);
+    Ok(())
 }
 
 /// Loads environment variables from a .env file if it exists
@@ -469,3 +734,48 @@ fn check_environment_variables() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_target_paths() {
+        let pdf = Path::new("/data/2024/invoice_42.pdf");
+        let relative = Path::new("2024");
+
+        let target = OutputTarget::parse(Path::new("out/{stem}.json"), true).unwrap();
+        assert_eq!(
+            target.path_for(pdf, relative),
+            PathBuf::from("out/invoice_42.json")
+        );
+
+        let target = OutputTarget::parse(Path::new("out"), true).unwrap();
+        assert_eq!(
+            target.path_for(pdf, relative),
+            PathBuf::from("out/2024/invoice_42.json")
+        );
+
+        let target = OutputTarget::parse(Path::new("result.json"), false).unwrap();
+        assert_eq!(target.path_for(pdf, relative), PathBuf::from("result.json"));
+
+        // Every PDF in a directory would overwrite the same file
+        let error = OutputTarget::parse(Path::new("result.json"), true).unwrap_err();
+        assert!(error.to_string().contains("{stem}"), "{}", error);
+    }
+
+    #[test]
+    fn test_write_output_refuses_to_overwrite() {
+        let dir = std::env::temp_dir().join("hvertex-test-write-output");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("nested/invoice_42.json");
+
+        write_output(&path, "{}", false).unwrap();
+        let error = write_output(&path, "{\"a\": 1}", false).unwrap_err();
+        assert!(error.to_string().contains("--force"), "{}", error);
+        write_output(&path, "{\"a\": 1}", true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"a\": 1}");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
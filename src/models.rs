@@ -1,8 +1,76 @@
 use anyhow::{Context, Result};
-use std::process::Command;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::gcloud::run_gcloud;
+use crate::vertex_ai::{VertexAIModel, VertexError};
+
+#[cfg(feature = "async")]
+use crate::auth::TokenProvider;
+#[cfg(feature = "async")]
+use crate::setup::SUPPORTED_REGIONS;
+#[cfg(feature = "async")]
+use crate::transport::{HttpTransport, Transport};
+#[cfg(feature = "async")]
+use crate::vertex_ai::{
+    api_base_url, build_http_client, endpoint_override_from_env, error_for_status, request_headers,
+    ModelEndpoint,
+};
+#[cfg(feature = "async")]
+use futures::stream::{self, StreamExt};
+#[cfg(feature = "async")]
+use reqwest::header::HeaderMap;
+#[cfg(feature = "async")]
+use serde::{de::DeserializeOwned, Deserialize};
+
+/// Number of models requested per page from the model list endpoints
+#[cfg(feature = "async")]
+const MODELS_PAGE_SIZE: u32 = 100;
+
+/// Most regions [`model_regions`] queries at once
+#[cfg(feature = "async")]
+const REGION_LOOKUP_CONCURRENCY: usize = 8;
+
+/// One page of results from the publisher models endpoint
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg(feature = "async")]
+struct PublisherModelsPage {
+    #[serde(default)]
+    publisher_models: Vec<VertexAIModel>,
+    #[serde(default)]
+    next_page_token: Option<String>,
+}
+
+/// One page of results from the project models endpoint
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg(feature = "async")]
+struct ModelsPage {
+    #[serde(default)]
+    models: Vec<VertexAIModel>,
+    #[serde(default)]
+    next_page_token: Option<String>,
+}
+
+/// How long [`list_vertex_ai_models`] reuses a model list before listing again
+pub const DEFAULT_MODEL_LIST_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Model lists by `(project_id, region)`, with the time they were fetched
+type ModelListCache = Mutex<HashMap<(String, String), (Instant, Vec<String>)>>;
+
+/// Regions serving a model by `(project_id, model_id)`, with the time they were looked up
+#[cfg(feature = "async")]
+type ModelRegionsCache = Mutex<HashMap<(String, String), (Instant, Vec<String>)>>;
 
 /// Lists available Vertex AI models in the project
 ///
+/// Lists are cached in-process for `DEFAULT_MODEL_LIST_TTL`, since running
+/// gcloud takes seconds and models rarely change within a session. Use
+/// [`list_vertex_ai_models_with_ttl`] to choose the TTL or bypass the cache.
+///
 /// # Arguments
 ///
 /// * `project_id` - The Google Cloud project ID
@@ -23,20 +91,78 @@ use std::process::Command;
 /// Ok::<(), Box<dyn std::error::Error>>(())
 /// ```
 pub fn list_vertex_ai_models(project_id: &str, region: &str) -> Result<Vec<String>> {
+    list_vertex_ai_models_with_ttl(project_id, region, DEFAULT_MODEL_LIST_TTL, false)
+}
+
+/// Lists available Vertex AI models in the project, caching them for `ttl`
+///
+/// # Arguments
+///
+/// * `project_id` - The Google Cloud project ID
+/// * `region` - The region to list models from
+/// * `ttl` - How long a cached list for the same project and region is reused
+/// * `force_refresh` - Lists the models again even if a cached list is fresh
+///
+/// # Returns
+///
+/// * `Result<Vec<String>, anyhow::Error>` - List of model names or error
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::models::list_vertex_ai_models_with_ttl;
+/// use std::time::Duration;
+///
+/// // Pick up a model deployed a moment ago
+/// let models =
+///     list_vertex_ai_models_with_ttl("my-project-id", "us-central1", Duration::from_secs(60), true)?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn list_vertex_ai_models_with_ttl(
+    project_id: &str,
+    region: &str,
+    ttl: Duration,
+    force_refresh: bool,
+) -> Result<Vec<String>> {
+    static CACHE: OnceLock<ModelListCache> = OnceLock::new();
+    let cache = CACHE.get_or_init(Default::default);
+    let key = (project_id.to_string(), region.to_string());
+
+    if !force_refresh {
+        let cached = cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(&key)
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < ttl)
+            .map(|(_, models)| models.clone());
+        if let Some(models) = cached {
+            debug!(project_id, region, "Using cached model list");
+            return Ok(models);
+        }
+    }
+
+    // Failed listings are not cached
+    let models = fetch_model_names(project_id, region)?;
+    cache
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(key, (Instant::now(), models.clone()));
+    Ok(models)
+}
+
+/// Lists the model names in a project and region with `gcloud ai models list`
+fn fetch_model_names(project_id: &str, region: &str) -> Result<Vec<String>> {
     // List Vertex AI models
-    let output = Command::new("gcloud")
-        .args([
-            "ai",
-            "models",
-            "list",
-            "--region",
-            region,
-            "--project",
-            project_id,
-            "--format=json",
-        ])
-        .output()
-        .context("Failed to execute gcloud ai models list command")?;
+    let output = run_gcloud(&[
+        "ai",
+        "models",
+        "list",
+        "--region",
+        region,
+        "--project",
+        project_id,
+        "--format=json",
+    ])?;
 
     if !output.status.success() {
         let error = String::from_utf8_lossy(&output.stderr);
@@ -47,8 +173,9 @@ pub fn list_vertex_ai_models(project_id: &str, region: &str) -> Result<Vec<Strin
     }
 
     // Parse the JSON output
-    let models: Vec<serde_json::Value> =
-        serde_json::from_slice(&output.stdout).context("Failed to parse models list output")?;
+    let models: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)
+        .map_err(|e| VertexError::Parse(e.to_string()))
+        .context("Failed to parse models list output")?;
 
     // Extract model names
     let model_names = models
@@ -59,3 +186,446 @@ pub fn list_vertex_ai_models(project_id: &str, region: &str) -> Result<Vec<Strin
 
     Ok(model_names)
 }
+
+/// Lists the models uploaded to a project, using the REST API instead of gcloud
+///
+/// Returns the same models as [`list_vertex_ai_models`], with their details,
+/// without blocking or needing the Google Cloud SDK installed. Follows
+/// `nextPageToken` until every page has been fetched.
+///
+/// # Arguments
+///
+/// * `project_id` - The Google Cloud project ID
+/// * `region` - The region to list models from
+/// * `token` - An OAuth access token with the cloud-platform scope
+///
+/// # Returns
+///
+/// * `Result<Vec<VertexAIModel>, anyhow::Error>` - All models in the region or error
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::auth::TokenProvider;
+/// use hvertex::models::list_vertex_ai_models_async;
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let token = TokenProvider::shared(None).token().await?;
+/// for model in list_vertex_ai_models_async("my-project-id", "us-central1", &token).await? {
+///     println!("{} ({})", model.display_name, model.name);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub async fn list_vertex_ai_models_async(
+    project_id: &str,
+    region: &str,
+    token: &str,
+) -> Result<Vec<VertexAIModel>> {
+    crate::setup::validate_region(region)?;
+
+    let headers = request_headers(token, Some(project_id), &[])?;
+    let api_url = format!(
+        "{}/v1/projects/{}/locations/{}/models",
+        api_base_url(region, endpoint_override_from_env()?.as_deref()),
+        project_id,
+        region
+    );
+
+    fetch_model_pages(&api_url, headers, "models", |page: ModelsPage| {
+        (page.models, page.next_page_token)
+    })
+    .await
+}
+
+/// GETs every page of a model list endpoint
+///
+/// `split` returns the models on a page and the token of the next page, if any.
+#[cfg(feature = "async")]
+async fn fetch_model_pages<P: DeserializeOwned>(
+    api_url: &str,
+    headers: HeaderMap,
+    what: &str,
+    split: impl Fn(P) -> (Vec<VertexAIModel>, Option<String>),
+) -> Result<Vec<VertexAIModel>> {
+    let client = build_http_client(None)?;
+
+    let mut models = Vec::new();
+    let mut page_token: Option<String> = None;
+    loop {
+        let mut request = client
+            .get(api_url)
+            .headers(headers.clone())
+            .query(&[("pageSize", MODELS_PAGE_SIZE.to_string())]);
+        if let Some(token) = &page_token {
+            request = request.query(&[("pageToken", token)]);
+        }
+
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to request {}", what))?;
+        let page: P = error_for_status(response)
+            .await?
+            .json()
+            .await
+            .map_err(|e| VertexError::Parse(e.to_string()))
+            .with_context(|| format!("Failed to parse {} response", what))?;
+
+        let (page_models, next_page_token) = split(page);
+        models.extend(page_models);
+        match next_page_token {
+            Some(token) if !token.is_empty() => page_token = Some(token),
+            _ => break,
+        }
+    }
+
+    Ok(models)
+}
+
+/// Lists the Google publisher models (e.g., Gemini) available in a region
+///
+/// Unlike [`list_vertex_ai_models`], which only returns models uploaded to the
+/// project, this queries the publisher models REST endpoint and follows
+/// `nextPageToken` until every page has been fetched. The list method is only
+/// served by the `v1beta1` API.
+///
+/// # Arguments
+///
+/// * `project_id` - The Google Cloud project ID (billed for the request quota)
+/// * `region` - The region to list models from
+///
+/// # Returns
+///
+/// * `Result<Vec<VertexAIModel>, anyhow::Error>` - All publisher models or error
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::models::list_publisher_models;
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// for model in list_publisher_models("my-project-id", "us-central1").await? {
+///     println!("{}", model.name);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub async fn list_publisher_models(project_id: &str, region: &str) -> Result<Vec<VertexAIModel>> {
+    crate::setup::validate_region(region)?;
+
+    let access_token = TokenProvider::shared(None).token().await?;
+    let headers = request_headers(&access_token, Some(project_id), &[])?;
+    let api_url = format!(
+        "{}/v1beta1/publishers/google/models",
+        api_base_url(region, endpoint_override_from_env()?.as_deref())
+    );
+
+    fetch_model_pages(
+        &api_url,
+        headers,
+        "publisher models",
+        |page: PublisherModelsPage| (page.publisher_models, page.next_page_token),
+    )
+    .await
+}
+
+/// Lists the regions where a publisher model is available
+///
+/// Looks the model up in every region in `SUPPORTED_REGIONS`, a few at a time.
+/// Regions whose lookup fails are skipped; an error is returned only if every
+/// lookup fails.
+///
+/// # Arguments
+///
+/// * `project_id` - The Google Cloud project ID
+/// * `model_id` - The model ID (e.g., "gemini-2.0-flash")
+///
+/// # Returns
+///
+/// * `Result<Vec<String>, anyhow::Error>` - The regions, in `SUPPORTED_REGIONS` order
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::models::model_regions;
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let regions = model_regions("my-project-id", "gemini-2.0-flash").await?;
+/// println!("Available in: {}", regions.join(", "));
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub async fn model_regions(project_id: &str, model_id: &str) -> Result<Vec<String>> {
+    let endpoint = ModelEndpoint::resolve(Some(project_id.to_string()), None, Some(model_id))?;
+    model_regions_with(&HttpTransport::for_endpoint(&endpoint, None), &endpoint).await
+}
+
+/// Lists the regions where `endpoint`'s model is available, through `transport`
+///
+/// Like [`model_regions`], but authenticates with the transport's identity,
+/// quota project, and extra headers. Results are cached in-process for
+/// `DEFAULT_MODEL_LIST_TTL` per project and model, so repeated failures do not
+/// query every region again. Fails without sending anything if the endpoint
+/// uses a private endpoint override, which only serves its own region.
+#[cfg(feature = "async")]
+pub async fn model_regions_with<T: Transport>(
+    transport: &T,
+    endpoint: &ModelEndpoint,
+) -> Result<Vec<String>> {
+    static CACHE: OnceLock<ModelRegionsCache> = OnceLock::new();
+
+    if endpoint.endpoint_override.is_some() {
+        anyhow::bail!("The regions of a model cannot be looked up through a private endpoint");
+    }
+
+    let key = (endpoint.project_id.clone(), endpoint.model_id.clone());
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some((fetched_at, regions)) = cache
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get(&key)
+    {
+        if fetched_at.elapsed() < DEFAULT_MODEL_LIST_TTL {
+            return Ok(regions.clone());
+        }
+    }
+
+    let lookups: Vec<(usize, Result<bool>)> = stream::iter(SUPPORTED_REGIONS.iter().enumerate())
+        .map(|(index, region)| async move {
+            let url = format!(
+                "{}/v1/publishers/google/models/{}",
+                api_base_url(region, None),
+                endpoint.model_id
+            );
+            let found = match transport.get(&url).await {
+                Ok(_) => Ok(true),
+                Err(e) => match e.downcast_ref() {
+                    Some(VertexError::Api { status: 404, .. }) => Ok(false),
+                    _ => Err(e),
+                },
+            };
+            (index, found)
+        })
+        .buffer_unordered(REGION_LOOKUP_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut found = Vec::new();
+    let mut errors = Vec::new();
+    for (index, result) in lookups {
+        match result {
+            Ok(true) => found.push(index),
+            Ok(false) => {}
+            Err(e) => errors.push(e),
+        }
+    }
+    let complete = errors.is_empty();
+    // Only fail when no region could be checked at all
+    if errors.len() == SUPPORTED_REGIONS.len() {
+        if let Some(error) = errors.into_iter().next() {
+            return Err(error.context(format!(
+                "Failed to look up the regions of {}",
+                endpoint.model_id
+            )));
+        }
+    }
+
+    found.sort_unstable();
+    let regions: Vec<String> = found
+        .into_iter()
+        .map(|index| SUPPORTED_REGIONS[index].to_string())
+        .collect();
+    // A partial answer is not cached, so skipped regions are checked next time
+    if complete {
+        cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(key, (Instant::now(), regions.clone()));
+    }
+    Ok(regions)
+}
+
+/// Criteria for selecting models with [`find_models`]
+///
+/// Unset criteria match every model.
+#[derive(Debug, Clone, Default)]
+pub struct ModelFilter {
+    /// A generation method the model must support (e.g., "generateContent")
+    pub generation_method: Option<String>,
+    /// The minimum input token limit the model must publish
+    pub min_input_tokens: Option<u32>,
+}
+
+impl ModelFilter {
+    /// Returns true if the model satisfies every criterion
+    ///
+    /// Models that do not publish an input token limit never satisfy `min_input_tokens`.
+    pub fn matches(&self, model: &VertexAIModel) -> bool {
+        let method_ok = self
+            .generation_method
+            .as_ref()
+            .is_none_or(|method| model.supported_generation_methods.contains(method));
+        let tokens_ok = self
+            .min_input_tokens
+            .is_none_or(|min| model.input_token_limit.is_some_and(|limit| limit >= min));
+        method_ok && tokens_ok
+    }
+}
+
+/// Lists the publisher models in a region that match a filter
+///
+/// # Arguments
+///
+/// * `project_id` - The Google Cloud project ID
+/// * `region` - The region to list models from
+/// * `filter` - The capabilities the models must have
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::models::{find_models, ModelFilter};
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let filter = ModelFilter {
+///     generation_method: Some("generateContent".to_string()),
+///     min_input_tokens: Some(1_000_000),
+/// };
+/// let models = find_models("my-project-id", "us-central1", filter).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub async fn find_models(
+    project_id: &str,
+    region: &str,
+    filter: ModelFilter,
+) -> Result<Vec<VertexAIModel>> {
+    let models = list_publisher_models(project_id, region).await?;
+    Ok(models
+        .into_iter()
+        .filter(|model| filter.matches(model))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gcloud::{set_command_runner, MockCommandRunner};
+    use std::rc::Rc;
+
+    #[test]
+    fn test_model_list_is_cached_within_ttl() {
+        let runner = Rc::new(MockCommandRunner::new());
+        for _ in 0..3 {
+            runner.push_output(r#"[{"name": "projects/1/locations/us-central1/models/42"}]"#);
+        }
+        let _guard = set_command_runner(runner.clone());
+        let list = |ttl, force_refresh| {
+            list_vertex_ai_models_with_ttl("cache-test-project", "us-central1", ttl, force_refresh)
+        };
+
+        let models = list(DEFAULT_MODEL_LIST_TTL, false).unwrap();
+        assert_eq!(models, ["projects/1/locations/us-central1/models/42"]);
+        assert_eq!(list(DEFAULT_MODEL_LIST_TTL, false).unwrap(), models);
+        assert_eq!(
+            runner.calls(),
+            ["gcloud ai models list --region us-central1 --project cache-test-project --format=json"]
+        );
+
+        // Forcing a refresh or an expired entry lists the models again
+        list(DEFAULT_MODEL_LIST_TTL, true).unwrap();
+        list(Duration::ZERO, false).unwrap();
+        assert_eq!(runner.calls().len(), 3);
+    }
+
+    #[test]
+    fn test_empty_model_lists() {
+        let runner = Rc::new(MockCommandRunner::new());
+        runner.push_output("[]");
+        runner.push_failure("Listed 0 items.\nERROR: could not find any resources");
+        runner.push_failure("ERROR: (gcloud.ai.models.list) PERMISSION_DENIED");
+        let _guard = set_command_runner(runner.clone());
+
+        assert!(fetch_model_names("empty-project", "us-central1")
+            .unwrap()
+            .is_empty());
+        assert!(fetch_model_names("empty-project", "us-central1")
+            .unwrap()
+            .is_empty());
+        let error = fetch_model_names("empty-project", "us-central1").unwrap_err();
+        assert!(error.to_string().contains("PERMISSION_DENIED"));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_publisher_models_page_parsing() {
+        let page: PublisherModelsPage = serde_json::from_str(
+            r#"{
+                "publisherModels": [
+                    {"name": "publishers/google/models/gemini-1.5-pro", "versionId": "002"},
+                    {"name": "publishers/google/models/gemini-2.0-flash", "displayName": "Gemini 2.0 Flash"}
+                ],
+                "nextPageToken": "abc"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(page.publisher_models.len(), 2);
+        assert_eq!(page.publisher_models[1].display_name, "Gemini 2.0 Flash");
+        assert_eq!(page.next_page_token.as_deref(), Some("abc"));
+
+        let last: PublisherModelsPage = serde_json::from_str("{}").unwrap();
+        assert!(last.publisher_models.is_empty() && last.next_page_token.is_none());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_models_page_parsing() {
+        let page: ModelsPage = serde_json::from_str(
+            r#"{
+                "models": [{
+                    "name": "projects/123/locations/us-central1/models/456",
+                    "displayName": "invoice-classifier",
+                    "versionId": "1"
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(page.models[0].display_name, "invoice-classifier");
+        assert!(page.next_page_token.is_none());
+    }
+
+    #[test]
+    fn test_model_filter() {
+        let model: VertexAIModel = serde_json::from_str(
+            r#"{
+                "name": "publishers/google/models/gemini-1.5-pro",
+                "supportedGenerationMethods": ["generateContent", "countTokens"],
+                "inputTokenLimit": 2097152
+            }"#,
+        )
+        .unwrap();
+
+        assert!(ModelFilter::default().matches(&model));
+        assert!(ModelFilter {
+            generation_method: Some("countTokens".to_string()),
+            min_input_tokens: Some(1_000_000),
+        }
+        .matches(&model));
+        assert!(!ModelFilter {
+            generation_method: Some("embedContent".to_string()),
+            ..ModelFilter::default()
+        }
+        .matches(&model));
+        assert!(!ModelFilter {
+            min_input_tokens: Some(3_000_000),
+            ..ModelFilter::default()
+        }
+        .matches(&model));
+    }
+}
@@ -1,12 +1,261 @@
 use anyhow::{Context, Result};
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
-use serde_json::{json, Value};
-use std::env;
+use base64::engine::general_purpose;
+use base64::Engine;
+use futures::StreamExt;
+use jsonschema::JSONSchema;
+use regex::Regex;
+use reqwest::header::CONTENT_TYPE;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, instrument, warn};
 
-use crate::auth;
-use crate::vertex_ai::VertexAIRequest;
+use crate::config::Config;
+use crate::queue::RequestQueue;
+use crate::stream::{StreamTextCollector, StreamWriter};
+use crate::transport::{HttpTransport, Transport};
+use crate::vertex_ai::{
+    build_http_client, error_for_status, explain_not_found, generate_content_with,
+    language_directive, parse_generated_json, parse_strict_json, send_with_retry, transport_error,
+    validate_base64, GenerateContentResponse, ModelEndpoint, PartOrder, RetryPolicy, UsageMetadata,
+    VertexAIRequest, VertexError, DEFAULT_EXTRACTION_PROMPT, DEFAULT_SYSTEM_INSTRUCTION,
+    MAX_INLINE_DATA_BYTES, SUPPORTED_MIME_TYPES,
+};
 
-/// Modified extract_data_from_pdf function to use the new VertexAIRequest struct
+/// Optional settings for extracting data from a file
+///
+/// Every field falls back to the same default as [`extract_data_from_pdf_v2`] when None.
+/// Set the common fields with the builder methods and the rest with struct
+/// update syntax.
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::pdf::ExtractionOptions;
+/// use std::time::Duration;
+///
+/// let options = ExtractionOptions::default()
+///     .prompt("List the parties and the contract number as JSON")
+///     .model("gemini-2.0-flash")
+///     .region("europe-west4")
+///     .timeout(Duration::from_secs(60));
+/// assert_eq!(options.location_id.as_deref(), Some("europe-west4"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ExtractionOptions {
+    /// Custom extraction prompt
+    pub prompt: Option<String>,
+    /// Custom system instruction
+    pub system_instruction: Option<String>,
+    /// The Google Cloud project ID (falls back to `VERTEX_AI_PROJECT_ID`)
+    pub project_id: Option<String>,
+    /// The location of the endpoint (defaults to "us-central1")
+    pub location_id: Option<String>,
+    /// The model to use (defaults to "gemini-2.0-flash-exp")
+    pub model_id: Option<String>,
+    /// The request timeout (defaults to 120 seconds)
+    pub timeout: Option<Duration>,
+    /// Service account to impersonate when fetching the access token
+    pub impersonate: Option<String>,
+    /// Largest document [`extract_data_from_url`] will download, in bytes
+    /// (defaults to `DEFAULT_MAX_DOWNLOAD_BYTES`)
+    pub max_download_bytes: Option<usize>,
+    /// Cancels the extraction, returning `VertexError::Cancelled`, when triggered
+    pub cancellation: Option<CancellationToken>,
+    /// Whether the prompt is sent before or after the document
+    pub part_order: PartOrder,
+    /// Project billed for quota, sent as the `X-Goog-User-Project` header
+    pub quota_project: Option<String>,
+    /// Additional headers sent with every request
+    pub headers: Vec<(String, String)>,
+    /// The API version, "v1" or "v1beta1" (defaults to "v1")
+    pub api_version: Option<String>,
+    /// https URL of a private endpoint used instead of the public API host
+    /// (falls back to `VERTEX_AI_ENDPOINT_OVERRIDE`)
+    pub endpoint_override: Option<String>,
+    /// Directory caching results by document, prompt, system instruction, and
+    /// model, so extracting the same document again skips the API call
+    pub cache_dir: Option<PathBuf>,
+    /// Ignores cached results (they are still refreshed) when `cache_dir` is set
+    pub force_refresh: bool,
+    /// Continues generation when the output hits `maxOutputTokens`, instead of
+    /// failing with `VertexError::FinishReason`
+    pub auto_continue: bool,
+    /// Most follow-up requests `auto_continue` may send (defaults to
+    /// `DEFAULT_MAX_CONTINUATIONS`)
+    pub max_continuations: Option<u32>,
+    /// JSONL manifest [`extract_batch`] appends each completed document to, so
+    /// [`extract_batch_resume`] can skip it after a restart
+    pub manifest: Option<PathBuf>,
+    /// ISO 639-1 code of the document's language (e.g., "fr"), appended to the
+    /// system instruction with [`language_directive`]
+    pub language: Option<String>,
+    /// Number of candidates to generate; the first whose output is valid JSON
+    /// is used (not applied to streaming extraction)
+    pub candidate_count: Option<u32>,
+}
+
+impl ExtractionOptions {
+    /// Sets the extraction prompt
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
+
+    /// Sets the system instruction
+    pub fn system_instruction(mut self, system_instruction: impl Into<String>) -> Self {
+        self.system_instruction = Some(system_instruction.into());
+        self
+    }
+
+    /// Sets the Google Cloud project ID
+    pub fn project_id(mut self, project_id: impl Into<String>) -> Self {
+        self.project_id = Some(project_id.into());
+        self
+    }
+
+    /// Sets the region of the endpoint (e.g., "europe-west4")
+    pub fn region(mut self, region: impl Into<String>) -> Self {
+        self.location_id = Some(region.into());
+        self
+    }
+
+    /// Sets the model (e.g., "gemini-2.0-flash")
+    pub fn model(mut self, model_id: impl Into<String>) -> Self {
+        self.model_id = Some(model_id.into());
+        self
+    }
+
+    /// Sets the request timeout
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the document's language (e.g., "de"), checked when extracting
+    pub fn language(mut self, code: impl Into<String>) -> Self {
+        self.language = Some(code.into());
+        self
+    }
+
+    /// Sets how many candidates to generate, keeping the first valid JSON one
+    pub fn candidate_count(mut self, candidate_count: u32) -> Self {
+        self.candidate_count = Some(candidate_count);
+        self
+    }
+
+    /// Returns the system instruction to send, with the language directive appended
+    ///
+    /// Returns a `ConfigError` if `language` is not in `SUPPORTED_LANGUAGES`.
+    fn system_instruction_with_language(&self) -> Result<Option<String>> {
+        let Some(language) = &self.language else {
+            return Ok(self.system_instruction.clone());
+        };
+        let system_instruction = self
+            .system_instruction
+            .as_deref()
+            .unwrap_or(DEFAULT_SYSTEM_INSTRUCTION);
+        Ok(Some(format!(
+            "{} {}",
+            system_instruction,
+            language_directive(language)?
+        )))
+    }
+
+    /// Asks for `candidate_count` candidates in `request`, when set
+    fn apply_candidate_count(&self, request: VertexAIRequest) -> VertexAIRequest {
+        match self.candidate_count {
+            Some(count) => request.with_candidate_count(count),
+            None => request,
+        }
+    }
+
+    /// Returns how many times a truncated response may be continued
+    fn continuations(&self) -> u32 {
+        if self.auto_continue {
+            self.max_continuations.unwrap_or(DEFAULT_MAX_CONTINUATIONS)
+        } else {
+            0
+        }
+    }
+
+    /// Resolves the endpoint the options point at
+    fn endpoint(&self) -> Result<ModelEndpoint> {
+        ModelEndpoint::resolve(
+            self.project_id.clone(),
+            self.location_id.as_deref(),
+            self.model_id.as_deref(),
+        )?
+        .with_impersonation(self.impersonate.clone())
+        .with_quota_project(self.quota_project.clone())
+        .with_headers(self.headers.clone())
+        .with_api_version(self.api_version.as_deref())?
+        .with_endpoint_override(self.endpoint_override.as_deref())
+    }
+}
+
+/// Default number of follow-up requests sent when `auto_continue` is enabled
+pub const DEFAULT_MAX_CONTINUATIONS: u32 = 3;
+
+/// The user message asking the model to resume a truncated response
+const CONTINUE_PROMPT: &str =
+    "Continue exactly where you stopped. Do not repeat anything or add any commentary.";
+
+/// Document bytes read per chunk by [`extract_data_from_reader`]
+///
+/// A multiple of 3, so every chunk but the last base64-encodes without padding.
+pub const STREAM_CHUNK_BYTES: usize = 3 * 64 * 1024;
+
+/// Placeholder for the document data in a streamed request body
+const STREAMED_DATA_MARKER: &str = "__hvertex_streamed_document__";
+
+/// Default download limit for [`extract_data_from_url`] (the inline request size limit)
+pub const DEFAULT_MAX_DOWNLOAD_BYTES: usize = 20 * 1024 * 1024;
+
+/// Detects the MIME type of a document from its magic bytes
+///
+/// Recognizes PDF, PNG, JPEG, and GIF data. Returns `None` for anything else.
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::pdf::detect_mime_type;
+///
+/// assert_eq!(detect_mime_type(b"%PDF-1.7"), Some("application/pdf"));
+/// assert_eq!(detect_mime_type(b"plain text"), None);
+/// ```
+pub fn detect_mime_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else {
+        None
+    }
+}
+
+/// Extracts data from a base64-encoded PDF with positional settings
+///
+/// Kept for compatibility; use [`extract_data_from_pdf_with_options`], which
+/// takes the same settings as an [`ExtractionOptions`]. The same-named
+/// `vertex_ai::extract_data_from_pdf_v2` is an older blocking implementation;
+/// it does not retry transient failures.
+#[deprecated(note = "use `extract_data_from_pdf_with_options` with an `ExtractionOptions`")]
+#[instrument(skip_all, fields(project_id = ?project_id, model = ?model_id))]
 pub async fn extract_data_from_pdf_v2(
     pdf_base64: &str,
     prompt: Option<&str>,
@@ -14,104 +263,2763 @@ pub async fn extract_data_from_pdf_v2(
     project_id: Option<String>,
     location_id: Option<&str>,
     model_id: Option<&str>,
+    timeout: Option<Duration>,
 ) -> Result<serde_json::Value> {
-    // Get the project ID, location ID, and model ID with default values
-    let project_id = match project_id {
-        Some(id) => id,
-        None => env::var("VERTEX_AI_PROJECT_ID")
-            .context("Project ID not provided and VERTEX_AI_PROJECT_ID not set")?,
+    let options = ExtractionOptions {
+        prompt: prompt.map(String::from),
+        system_instruction: system_instruction.map(String::from),
+        project_id,
+        location_id: location_id.map(String::from),
+        model_id: model_id.map(String::from),
+        timeout,
+        ..ExtractionOptions::default()
     };
-    let location_id = location_id.unwrap_or("us-central1");
-    let model_id = model_id.unwrap_or("gemini-2.0-flash-exp");
-    let api_endpoint = format!("{}-aiplatform.googleapis.com", location_id);
+    extract_data_from_pdf_with_options(pdf_base64, &options).await
+}
 
-    println!("Extracting data from PDF using Vertex AI {}...", model_id);
+/// Extracts data from a base64-encoded PDF using [`ExtractionOptions`]
+///
+/// This is the async version and the one to use from async code. Callers
+/// without a tokio runtime should use [`extract_data_from_pdf_v2_blocking`].
+/// Triggering `options.cancellation` aborts the in-flight request and returns
+/// `VertexError::Cancelled` promptly.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::{extract_data_from_pdf_with_options, ExtractionOptions};
+/// use tokio_util::sync::CancellationToken;
+///
+/// # async fn run(pdf_base64: &str) -> anyhow::Result<()> {
+/// let token = CancellationToken::new();
+/// let options = ExtractionOptions {
+///     cancellation: Some(token.clone()),
+///     ..ExtractionOptions::default().model("gemini-2.0-flash")
+/// };
+///
+/// // e.g., call token.cancel() when the client disconnects
+/// let data = extract_data_from_pdf_with_options(pdf_base64, &options).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn extract_data_from_pdf_with_options(
+    pdf_base64: &str,
+    options: &ExtractionOptions,
+) -> Result<serde_json::Value> {
+    validate_base64(pdf_base64)?;
+    let bytes = general_purpose::STANDARD
+        .decode(pdf_base64)
+        .map_err(|e| VertexError::Parse(e.to_string()))?;
 
-    // Get access token for API authentication
-    let access_token = auth::get_access_token()?;
+    let (value, _) = extract_inline_with_usage(&bytes, "application/pdf", options.clone()).await?;
+    Ok(value)
+}
 
-    // Set up the HTTP client
-    let client = reqwest::Client::new();
+/// Runs `future` to completion unless `cancellation` is triggered first
+async fn cancellable<T>(
+    cancellation: Option<CancellationToken>,
+    future: impl std::future::Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(cancellation) = cancellation else {
+        return future.await;
+    };
 
-    // Construct the API URL
-    let api_url = format!(
-        "https://{}/v1/projects/{}/locations/{}/publishers/google/models/{}:generateContent",
-        api_endpoint, project_id, location_id, model_id
-    );
+    tokio::select! {
+        biased;
+        _ = cancellation.cancelled() => Err(VertexError::Cancelled.into()),
+        result = future => result,
+    }
+}
 
-    // Set up request headers
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        AUTHORIZATION,
-        HeaderValue::from_str(&format!("Bearer {}", access_token))
-            .context("Failed to create authorization header")?,
-    );
-    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+/// Blocking version of [`extract_data_from_pdf_with_options`] for callers without an async runtime
+///
+/// Runs the async extraction on a dedicated single-threaded tokio runtime, so it
+/// gets the same retries and parsing. It panics if called from inside a tokio
+/// runtime; await [`extract_data_from_pdf_with_options`] there instead.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::extract_data_from_pdf_v2_blocking;
+///
+/// # fn run(pdf_base64: &str) -> anyhow::Result<()> {
+/// let data = extract_data_from_pdf_v2_blocking(pdf_base64, None, None, None, None, None, None)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn extract_data_from_pdf_v2_blocking(
+    pdf_base64: &str,
+    prompt: Option<&str>,
+    system_instruction: Option<&str>,
+    project_id: Option<String>,
+    location_id: Option<&str>,
+    model_id: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<serde_json::Value> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start a tokio runtime for blocking extraction")?;
+
+    let options = ExtractionOptions {
+        prompt: prompt.map(String::from),
+        system_instruction: system_instruction.map(String::from),
+        project_id,
+        location_id: location_id.map(String::from),
+        model_id: model_id.map(String::from),
+        timeout,
+        ..ExtractionOptions::default()
+    };
+    runtime.block_on(extract_data_from_pdf_with_options(pdf_base64, &options))
+}
+
+/// Extracts data from a PDF and returns the token usage reported for the request
+///
+/// Takes the same arguments as [`extract_data_from_pdf_v2`]. The usage is all
+/// zeros if the API omits `usageMetadata`.
+///
+/// # Returns
+///
+/// * `Result<(serde_json::Value, UsageMetadata), anyhow::Error>` - The extracted
+///   data and its prompt, candidate, and total token counts
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::extract_data_from_pdf_with_usage;
+///
+/// # async fn run(pdf_base64: &str) -> anyhow::Result<()> {
+/// let (data, usage) =
+///     extract_data_from_pdf_with_usage(pdf_base64, None, None, None, None, None, None).await?;
+/// println!("Billed {} tokens", usage.total_token_count);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn extract_data_from_pdf_with_usage(
+    pdf_base64: &str,
+    prompt: Option<&str>,
+    system_instruction: Option<&str>,
+    project_id: Option<String>,
+    location_id: Option<&str>,
+    model_id: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<(serde_json::Value, UsageMetadata)> {
+    // Reject malformed input before making any network call
+    validate_base64(pdf_base64)?;
 
     // Use the default prompt or a custom one
-    let default_prompt = "read this file give all data in josn format. you need to be smart key mininingfull choise and a fild for acuracy score . in and contacrt there diifrent information related to girent people like adress of company or adresss of indidual who signe contarct. those need to be seperated.";
-    let prompt_text = prompt.unwrap_or(default_prompt);
+    let prompt_text = prompt.unwrap_or(DEFAULT_EXTRACTION_PROMPT);
 
     // Create the request using our new struct
     let request = VertexAIRequest::new_pdf_extraction(pdf_base64, prompt_text, system_instruction);
 
-    // Make the API request
+    // Get the project ID, location ID, and model ID with default values
+    let endpoint = ModelEndpoint::resolve(project_id, location_id, model_id)?;
+
+    extract_data_at_endpoint_with_usage(&request, &endpoint, timeout, 0).await
+}
+
+/// Extracts data from a document on disk
+///
+/// Reads the file, detects its MIME type from its contents (or from the
+/// extension for DOCX and WebP files), base64-encodes it, and sends it for
+/// extraction.
+///
+/// # Arguments
+///
+/// * `path` - Path to a PDF, image, or DOCX file
+/// * `options` - Optional prompt, model, and endpoint settings
+///
+/// # Returns
+///
+/// * `Result<serde_json::Value, anyhow::Error>` - The extracted data, or
+///   `VertexError::FileNotFound` / `VertexError::EmptyFile` for unusable input
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::{extract_data_from_path, ExtractionOptions};
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let data = extract_data_from_path("contract.pdf", &ExtractionOptions::default()).await?;
+/// println!("{}", serde_json::to_string_pretty(&data)?);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn extract_data_from_path(
+    path: impl AsRef<Path>,
+    options: &ExtractionOptions,
+) -> Result<serde_json::Value> {
+    let (value, _) = extract_data_from_path_with_usage(path.as_ref(), options.clone()).await?;
+    Ok(value)
+}
+
+/// Extracts data from a document read from `reader`, streaming it into the request
+///
+/// The document is base64-encoded chunk by chunk as the request body is sent,
+/// so neither the whole document nor its base64 text is held in memory. Each
+/// request in flight holds about `STREAM_CHUNK_BYTES` of the document plus
+/// its base64 encoding (under 512 KiB), in addition to the prompt and the
+/// response, regardless of the document's size.
+///
+/// The document must still fit in `MAX_INLINE_DATA_BYTES` once encoded; the
+/// upload is aborted with `VertexError::RequestTooLarge` as soon as it grows
+/// past that. For larger documents, upload them to Cloud Storage and use
+/// [`extract_data_from_gcs`], which sends only the `gs://` URI.
+///
+/// A streamed body cannot be replayed, so unlike [`extract_data_from_path`]
+/// the request is not retried, not cached, and not continued when it hits
+/// `maxOutputTokens`.
+///
+/// # Arguments
+///
+/// * `reader` - The document contents (e.g., a `tokio::fs::File`)
+/// * `mime_type` - The MIME type of the document (must be in `SUPPORTED_MIME_TYPES`)
+/// * `options` - Optional prompt, model, and endpoint settings
+///
+/// # Returns
+///
+/// * `Result<serde_json::Value, anyhow::Error>` - The extracted data or error
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::{extract_data_from_reader, ExtractionOptions};
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let file = tokio::fs::File::open("contract.pdf").await?;
+/// let data =
+///     extract_data_from_reader(file, "application/pdf", &ExtractionOptions::default()).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn extract_data_from_reader<R>(
+    reader: R,
+    mime_type: &str,
+    options: &ExtractionOptions,
+) -> Result<serde_json::Value>
+where
+    R: AsyncRead + Send + Sync + Unpin + 'static,
+{
+    let prompt = options
+        .prompt
+        .as_deref()
+        .unwrap_or(DEFAULT_EXTRACTION_PROMPT);
+    let endpoint = options.endpoint()?;
+
+    // Build the request around a marker, then stream the document in its place
+    let request = VertexAIRequest::new_inline_extraction(
+        STREAMED_DATA_MARKER,
+        mime_type,
+        prompt,
+        options.system_instruction_with_language()?.as_deref(),
+    )?
+    .with_part_order(options.part_order);
+    let request = options.apply_candidate_count(request);
+
+    let transport = HttpTransport::for_endpoint(&endpoint, options.timeout);
+    let result = cancellable(
+        options.cancellation.clone(),
+        send_streamed_extraction(&transport, &request, &endpoint, reader),
+    )
+    .await;
+    match result {
+        Err(e) => Err(explain_not_found(e, &transport, &endpoint).await),
+        result => result,
+    }
+}
+
+/// Sends an extraction request whose inline data is streamed from `reader`
+async fn send_streamed_extraction<R>(
+    transport: &HttpTransport,
+    request: &VertexAIRequest,
+    endpoint: &ModelEndpoint,
+    reader: R,
+) -> Result<serde_json::Value>
+where
+    R: AsyncRead + Send + Sync + Unpin + 'static,
+{
+    let body = serde_json::to_string(request)
+        .map_err(|e| VertexError::Parse(e.to_string()))
+        .context("Failed to serialize request")?;
+    let (prefix, suffix) = body
+        .split_once(STREAMED_DATA_MARKER)
+        .context("Streamed request body has no document placeholder")?;
+
+    let encoded = Arc::new(AtomicUsize::new(0));
+    let body = base64_body_stream(
+        reader,
+        prefix.to_string(),
+        suffix.to_string(),
+        encoded.clone(),
+    );
+
+    info!(
+        project_id = %endpoint.project_id,
+        model = %endpoint.model_id,
+        "Extracting data from a streamed document using Vertex AI"
+    );
+    let client = transport.client()?;
+    let headers = transport.headers().await?;
     let response = client
-        .post(api_url)
+        .post(endpoint.url("generateContent"))
         .headers(headers)
-        .json(&request)
+        .body(reqwest::Body::wrap_stream(body))
         .send()
+        .await;
+
+    // The body stream aborts the upload once the document is too large
+    let size = encoded.load(Ordering::SeqCst);
+    if size > MAX_INLINE_DATA_BYTES {
+        return Err(VertexError::RequestTooLarge {
+            size,
+            limit: MAX_INLINE_DATA_BYTES,
+        }
+        .into());
+    }
+    let response = response.map_err(|e| {
+        transport_error(e, transport.timeout, "Failed to make Vertex AI API request")
+    })?;
+    let response: GenerateContentResponse = error_for_status(response)
+        .await?
+        .json()
         .await
-        .context("Failed to make Vertex AI API request")?;
+        .map_err(|e| transport_error(e, transport.timeout, "Failed to parse API response"))?;
 
-    // Check if the request was successful
+    let text = best_candidate_text(&response)?;
+    if request.expects_json() {
+        parse_strict_json(&text)
+    } else {
+        Ok(parse_generated_json(&text))
+    }
+}
+
+/// Returns a request body made of `prefix`, the base64 of `reader`, and `suffix`
+///
+/// The document is read `STREAM_CHUNK_BYTES` at a time, and the running size of
+/// its base64 text is kept in `encoded`. The stream fails once that exceeds
+/// `MAX_INLINE_DATA_BYTES`.
+fn base64_body_stream<R>(
+    reader: R,
+    prefix: String,
+    suffix: String,
+    encoded: Arc<AtomicUsize>,
+) -> impl futures::Stream<Item = std::io::Result<Vec<u8>>> + Send + Sync + 'static
+where
+    R: AsyncRead + Send + Sync + Unpin + 'static,
+{
+    let data = futures::stream::try_unfold(reader, move |mut reader| {
+        let encoded = encoded.clone();
+        async move {
+            // Fill whole chunks so only the last one can need base64 padding
+            let mut chunk = vec![0; STREAM_CHUNK_BYTES];
+            let mut filled = 0;
+            while filled < chunk.len() {
+                match reader.read(&mut chunk[filled..]).await? {
+                    0 => break,
+                    read => filled += read,
+                }
+            }
+            if filled == 0 {
+                return Ok(None);
+            }
+
+            let text = general_purpose::STANDARD.encode(&chunk[..filled]);
+            let size = encoded.fetch_add(text.len(), Ordering::SeqCst) + text.len();
+            if size > MAX_INLINE_DATA_BYTES {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "Document exceeds the inline limit of {} bytes of base64",
+                        MAX_INLINE_DATA_BYTES
+                    ),
+                ));
+            }
+            Ok(Some((text.into_bytes(), reader)))
+        }
+    });
+
+    futures::stream::once(async move { Ok(prefix.into_bytes()) })
+        .chain(data)
+        .chain(futures::stream::once(
+            async move { Ok(suffix.into_bytes()) },
+        ))
+}
+
+/// Extracts data from a document on disk and deserializes it into `T`
+///
+/// Runs [`extract_data_from_path`], recovers JSON wrapped in Markdown code
+/// blocks with [`extract_json_from_raw_text`], and then deserializes the result.
+/// Describe the fields of `T` in `options.prompt` so the model returns them.
+///
+/// # Arguments
+///
+/// * `path` - Path to a PDF, image, or DOCX file
+/// * `options` - Optional prompt, model, and endpoint settings
+///
+/// # Returns
+///
+/// * `Result<T, anyhow::Error>` - The typed data, or `VertexError::Parse`
+///   including the offending JSON if it does not match `T`
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::{extract_typed, ExtractionOptions};
+/// use serde::Deserialize;
+///
+/// #[derive(Debug, Deserialize)]
+/// struct Contract {
+///     contract_number: String,
+///     parties: Vec<String>,
+/// }
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let contract: Contract = extract_typed("contract.pdf", &ExtractionOptions::default()).await?;
+/// println!("{} between {:?}", contract.contract_number, contract.parties);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn extract_typed<T: DeserializeOwned>(
+    path: impl AsRef<Path>,
+    options: &ExtractionOptions,
+) -> Result<T> {
+    let value = extract_data_from_path(path, options).await?;
+    deserialize_extracted(value)
+}
+
+/// Deserializes extracted data, unwrapping a `raw_text` fallback first
+fn deserialize_extracted<T: DeserializeOwned>(value: serde_json::Value) -> Result<T> {
+    let value = match value.get("raw_text").and_then(|v| v.as_str()) {
+        Some(raw_text) => extract_json_from_raw_text_with(raw_text, BlockSelection::Largest, true)
+            .context("The model did not return JSON that can be deserialized")?,
+        None => value,
+    };
+
+    serde_json::from_value(value.clone()).map_err(|e| {
+        VertexError::Parse(format!(
+            "Extracted data does not match {}: {}\n{}",
+            std::any::type_name::<T>(),
+            e,
+            serde_json::to_string_pretty(&value).unwrap_or_default()
+        ))
+        .into()
+    })
+}
+
+/// Downloads a document over HTTP(S) and extracts data from it
+///
+/// The download uses the shared client settings (timeout, proxy, extra CA
+/// certificates) and is aborted once it exceeds `options.max_download_bytes`.
+/// The response's `Content-Type` must be one of `SUPPORTED_MIME_TYPES`; a
+/// missing or `application/octet-stream` type falls back to the magic bytes.
+///
+/// # Arguments
+///
+/// * `url` - The http or https URL of the document (e.g., a signed URL)
+/// * `options` - Prompt, model, endpoint, and download settings
+///
+/// # Returns
+///
+/// * `Result<serde_json::Value, anyhow::Error>` - The extracted data, or
+///   `VertexError::Download` if the document could not be fetched
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::{extract_data_from_url, ExtractionOptions};
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let data = extract_data_from_url(
+///     "https://storage.googleapis.com/bucket/contract.pdf?X-Goog-Signature=...",
+///     &ExtractionOptions::default(),
+/// )
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn extract_data_from_url(
+    url: &str,
+    options: &ExtractionOptions,
+) -> Result<serde_json::Value> {
+    let (bytes, mime_type) = cancellable(
+        options.cancellation.clone(),
+        download_document(url, options),
+    )
+    .await?;
+    let (value, _) = extract_inline_with_usage(&bytes, mime_type, options.clone()).await?;
+    Ok(value)
+}
+
+/// Downloads a document, returning its bytes and MIME type
+async fn download_document(
+    url: &str,
+    options: &ExtractionOptions,
+) -> Result<(Vec<u8>, &'static str)> {
+    let logged_url = url_without_query(url);
+    let download_error = |reason: String| VertexError::Download {
+        url: logged_url.clone(),
+        reason,
+    };
+    let limit = options
+        .max_download_bytes
+        .unwrap_or(DEFAULT_MAX_DOWNLOAD_BYTES);
+
+    let response = build_http_client(options.timeout)?
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| download_error(e.without_url().to_string()))?;
     let status = response.status();
     if !status.is_success() {
-        // If the request failed, return the error
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unable to get error details".to_string());
+        return Err(download_error(format!("HTTP {}", status)).into());
+    }
+    if response
+        .content_length()
+        .is_some_and(|length| length > limit as u64)
+    {
+        return Err(download_error(format!("document is larger than {} bytes", limit)).into());
+    }
+
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase()
+        });
+
+    // Read incrementally so a missing or false Content-Length cannot exhaust memory
+    let mut bytes = Vec::new();
+    let mut body = response.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| download_error(e.without_url().to_string()))?;
+        if bytes.len() + chunk.len() > limit {
+            return Err(download_error(format!("document is larger than {} bytes", limit)).into());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    if bytes.is_empty() {
+        return Err(download_error("response body is empty".to_string()).into());
+    }
+
+    let mime_type = match content_type.as_deref() {
+        None | Some("application/octet-stream") => detect_mime_type(&bytes),
+        Some(content_type) => SUPPORTED_MIME_TYPES
+            .iter()
+            .find(|supported| **supported == content_type)
+            .copied(),
+    }
+    .ok_or_else(|| {
+        VertexError::UnsupportedMimeType(content_type.unwrap_or_else(|| "unknown".to_string()))
+    })?;
+
+    Ok((bytes, mime_type))
+}
+
+/// Returns `url` without its query string and fragment, for error messages
+///
+/// Signed URLs carry their credentials (e.g., `X-Goog-Signature`) in the query.
+fn url_without_query(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut parsed) => {
+            parsed.set_query(None);
+            parsed.set_fragment(None);
+            parsed.to_string()
+        }
+        Err(_) => url.split(['?', '#']).next().unwrap_or_default().to_string(),
+    }
+}
+
+/// Extracts data from several PDFs in a single request
+///
+/// Sends every PDF to the model at once, each labelled with its file name, so
+/// the prompt can ask for comparisons across documents (e.g., "Which clauses
+/// differ between the two contracts?").
+///
+/// # Arguments
+///
+/// * `paths` - The PDFs to send, in order
+/// * `options` - The prompt (`options.prompt`) and model settings
+///
+/// # Returns
+///
+/// * `Result<serde_json::Value, anyhow::Error>` - The extracted data, or
+///   `VertexError::RequestTooLarge` if the PDFs together are too large to send inline
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::{extract_from_multiple, ExtractionOptions};
+/// use std::path::PathBuf;
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let options = ExtractionOptions::default()
+///     .prompt("List every clause that differs between a.pdf and b.pdf as JSON.");
+/// let diff = extract_from_multiple(&[PathBuf::from("a.pdf"), PathBuf::from("b.pdf")], &options).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn extract_from_multiple(
+    paths: &[PathBuf],
+    options: &ExtractionOptions,
+) -> Result<serde_json::Value> {
+    let mut pdfs = Vec::with_capacity(paths.len());
+    for path in paths {
+        let (bytes, mime_type) = read_document(path)?;
+        if mime_type != "application/pdf" {
+            return Err(VertexError::UnsupportedMimeType(format!(
+                "{} ({}); only PDFs can be combined",
+                mime_type,
+                path.display()
+            ))
+            .into());
+        }
+        let label = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        pdfs.push((general_purpose::STANDARD.encode(bytes), label));
+    }
+
+    let pdfs: Vec<(&str, &str)> = pdfs
+        .iter()
+        .map(|(data, label)| (data.as_str(), label.as_str()))
+        .collect();
+    let request = VertexAIRequest::new_multi_pdf_extraction(
+        &pdfs,
+        options
+            .prompt
+            .as_deref()
+            .unwrap_or(DEFAULT_EXTRACTION_PROMPT),
+        options.system_instruction_with_language()?.as_deref(),
+    )?
+    .with_part_order(options.part_order);
+    let request = options.apply_candidate_count(request);
+
+    let endpoint = options.endpoint()?;
+
+    let (value, _) = cancellable(
+        options.cancellation.clone(),
+        extract_data_at_endpoint_with_usage(
+            &request,
+            &endpoint,
+            options.timeout,
+            options.continuations(),
+        ),
+    )
+    .await?;
+    Ok(value)
+}
+
+/// Reads a document from disk, returning its bytes and MIME type
+fn read_document(path: &Path) -> Result<(Vec<u8>, &'static str)> {
+    if !path.is_file() {
+        return Err(VertexError::FileNotFound(path.to_path_buf()).into());
+    }
+
+    let bytes = fs::read(path)
+        .map_err(VertexError::Io)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    if bytes.is_empty() {
+        return Err(VertexError::EmptyFile(path.to_path_buf()).into());
+    }
+
+    let mime_type = detect_mime_type(&bytes)
+        .or_else(|| mime_type_from_extension(path))
+        .ok_or_else(|| VertexError::UnsupportedMimeType(format!("unknown ({})", path.display())))?;
+
+    Ok((bytes, mime_type))
+}
+
+/// Extracts data from a document on disk, returning the token usage too
+async fn extract_data_from_path_with_usage(
+    path: &Path,
+    options: ExtractionOptions,
+) -> Result<(serde_json::Value, UsageMetadata)> {
+    let (bytes, mime_type) = read_document(path)?;
+    extract_inline_with_usage(&bytes, mime_type, options).await
+}
+
+/// Extracts data from document bytes of a known MIME type
+async fn extract_inline_with_usage(
+    bytes: &[u8],
+    mime_type: &str,
+    options: ExtractionOptions,
+) -> Result<(serde_json::Value, UsageMetadata)> {
+    let prompt = options
+        .prompt
+        .as_deref()
+        .unwrap_or(DEFAULT_EXTRACTION_PROMPT);
+    let system_instruction = options.system_instruction_with_language()?;
+    let endpoint = options.endpoint()?;
+
+    let cache_path = options.cache_dir.as_ref().map(|dir| {
+        dir.join(format!(
+            "{}.json",
+            cache_key(
+                bytes,
+                mime_type,
+                prompt,
+                system_instruction.as_deref(),
+                &endpoint.model_id,
+                options.part_order,
+                options.candidate_count,
+            )
+        ))
+    });
+    if let Some(path) = cache_path.as_ref().filter(|_| !options.force_refresh) {
+        if let Some(value) = read_cached(path) {
+            info!(path = %path.display(), "Using cached extraction");
+            // Nothing was sent, so no tokens were used
+            return Ok((value, UsageMetadata::default()));
+        }
+    }
+
+    let request = VertexAIRequest::new_inline_extraction(
+        &general_purpose::STANDARD.encode(bytes),
+        mime_type,
+        prompt,
+        system_instruction.as_deref(),
+    )?
+    .with_part_order(options.part_order);
+    let request = options.apply_candidate_count(request);
+
+    let continuations = options.continuations();
+    let (value, usage) = cancellable(
+        options.cancellation,
+        extract_data_at_endpoint_with_usage(&request, &endpoint, options.timeout, continuations),
+    )
+    .await?;
+
+    if let Some(path) = &cache_path {
+        // A failed cache write only costs a repeat API call later
+        if let Err(e) = write_cached(path, &value) {
+            warn!(path = %path.display(), error = %e, "Failed to cache extraction");
+        }
+    }
+    Ok((value, usage))
+}
+
+/// Returns the SHA-256 cache key for an extraction, as lowercase hex
+///
+/// Each input is length-prefixed so different inputs never hash the same bytes.
+/// Every setting that changes the request is included, so a result is never
+/// reused for a request that would have been sent differently.
+fn cache_key(
+    bytes: &[u8],
+    mime_type: &str,
+    prompt: &str,
+    system_instruction: Option<&str>,
+    model_id: &str,
+    part_order: PartOrder,
+    candidate_count: Option<u32>,
+) -> String {
+    let part_order = match part_order {
+        PartOrder::DataFirst => "data-first",
+        PartOrder::TextFirst => "text-first",
+    };
+    let candidate_count = candidate_count.map(|count| count.to_string());
+
+    let mut hasher = Sha256::new();
+    for field in [
+        bytes,
+        mime_type.as_bytes(),
+        prompt.as_bytes(),
+        system_instruction.unwrap_or_default().as_bytes(),
+        model_id.as_bytes(),
+        part_order.as_bytes(),
+        candidate_count.as_deref().unwrap_or_default().as_bytes(),
+    ] {
+        hasher.update((field.len() as u64).to_le_bytes());
+        hasher.update(field);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Reads a cached extraction, treating unreadable entries as missing
+fn read_cached(path: &Path) -> Option<serde_json::Value> {
+    let contents = fs::read(path).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+/// Stores an extraction in the cache
+///
+/// Writes to a temporary file first, so a concurrent reader never sees a
+/// partially written entry.
+fn write_cached(path: &Path, value: &serde_json::Value) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&temp_path, serde_json::to_vec(value)?)
+        .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+    fs::rename(&temp_path, path)
+        .with_context(|| format!("Failed to move {} into place", temp_path.display()))
+}
+
+/// Roughly estimates the tokens a document extraction will consume from the file size
+///
+/// Assumes about four bytes of document per input token, plus the maximum output budget.
+pub fn estimate_token_cost(document_len: usize) -> usize {
+    document_len / 4 + 8192
+}
+
+/// Progress of a single document in [`extract_batch`]
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    /// Extraction of the document has started
+    Started {
+        /// The document being extracted
+        path: PathBuf,
+    },
+    /// The document was extracted successfully
+    Completed {
+        /// The extracted document
+        path: PathBuf,
+        /// Total tokens billed for the document
+        tokens: u32,
+    },
+    /// Extraction of the document failed
+    Failed {
+        /// The document that failed
+        path: PathBuf,
+        /// The error, formatted with its causes
+        error: String,
+    },
+    /// The document was already in the manifest, so it was not extracted again
+    Skipped {
+        /// The skipped document
+        path: PathBuf,
+    },
+}
+
+/// A completed document recorded in a batch manifest
+///
+/// The manifest holds one entry per line as JSON, e.g.
+/// `{"path":"/data/a.pdf","output":"/runs/batch.outputs/a-1f3c0e9b2a4d.json"}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// The extracted document
+    pub path: PathBuf,
+    /// The file holding the extracted JSON
+    pub output: PathBuf,
+}
+
+/// Reads a batch manifest into a map from document path to output file
+///
+/// A missing manifest is empty. Lines that are not valid entries, such as a
+/// line cut short when the process died, are skipped with a warning.
+///
+/// # Arguments
+///
+/// * `manifest_path` - The JSONL manifest written by [`extract_batch`]
+///
+/// # Returns
+///
+/// * `Result<HashMap<PathBuf, PathBuf>, anyhow::Error>` - The recorded documents
+///   and their output files
+pub fn read_manifest(manifest_path: &Path) -> Result<HashMap<PathBuf, PathBuf>> {
+    let contents = match fs::read_to_string(manifest_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => {
+            return Err(VertexError::Io(e))
+                .with_context(|| format!("Failed to read {}", manifest_path.display()))
+        }
+    };
+
+    let mut entries = HashMap::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<ManifestEntry>(line) {
+            Ok(entry) => {
+                entries.insert(entry.path, entry.output);
+            }
+            Err(e) => warn!(
+                manifest = %manifest_path.display(),
+                line = index + 1,
+                error = %e,
+                "Skipping invalid manifest line"
+            ),
+        }
+    }
+    Ok(entries)
+}
+
+/// Returns the path a document is recorded under in a manifest
+///
+/// Paths are canonicalized so a restart from another working directory still
+/// matches; a path that cannot be resolved is recorded as given.
+fn manifest_key(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Returns the file a document's extracted JSON is written to
+///
+/// Outputs live in a directory next to the manifest (`batch.jsonl` uses
+/// `batch.outputs/`). The file name adds a hash of the document path, so
+/// documents with the same name in different directories do not collide.
+fn manifest_output_path(manifest_path: &Path, key: &Path) -> PathBuf {
+    let stem = key
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "document".to_string());
+    let digest = format!("{:x}", Sha256::digest(key.to_string_lossy().as_bytes()));
+    manifest_path
+        .with_extension("outputs")
+        .join(format!("{}-{}.json", stem, &digest[..12]))
+}
+
+/// Writes a document's extracted JSON and appends it to the manifest
+///
+/// The output is written before the manifest line, so every recorded entry
+/// points at a complete file.
+fn record_in_manifest(manifest_path: &Path, path: &Path, value: &serde_json::Value) -> Result<()> {
+    let key = manifest_key(path);
+    let output = manifest_output_path(manifest_path, &key);
+    write_cached(&output, value)?;
+
+    let mut line = serde_json::to_string(&ManifestEntry { path: key, output })?;
+    line.push('\n');
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(manifest_path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(VertexError::Io)
+        .with_context(|| format!("Failed to append to {}", manifest_path.display()))
+}
+
+/// Extracts data from many documents concurrently
+///
+/// Every extraction is sent through `queue`, charged with the
+/// [`estimate_token_cost`] of its base64 payload, so the queue's
+/// `max_concurrent_requests` and token bucket are the only limits on how hard
+/// the API is hit. A failed document does not abort the batch; every outcome is
+/// returned next to its path, in input order.
+///
+/// # Arguments
+///
+/// * `paths` - The documents to extract
+/// * `options` - Prompt, model, and endpoint settings shared by every document
+/// * `queue` - The rate limiter the batch runs under (it may be shared with other work)
+/// * `progress` - Optional channel that receives a [`ProgressEvent`] as each
+///   document starts and finishes
+///
+/// When `options.manifest` is set, each successful extraction is written to a
+/// file and recorded in the manifest (see [`extract_batch_resume`]). A failed
+/// manifest write is logged and does not fail the document.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::{extract_batch, ExtractionOptions, ProgressEvent};
+/// use hvertex::queue::{QueueConfig, RequestQueue};
+/// use std::path::PathBuf;
+///
+/// # async fn run() {
+/// let (sender, mut receiver) = tokio::sync::mpsc::channel(16);
+/// tokio::spawn(async move {
+///     while let Some(event) = receiver.recv().await {
+///         if let ProgressEvent::Completed { path, tokens } = event {
+///             println!("{}: {} tokens", path.display(), tokens);
+///         }
+///     }
+/// });
+///
+/// let paths = vec![PathBuf::from("a.pdf"), PathBuf::from("b.pdf")];
+/// let queue = RequestQueue::new(QueueConfig::for_model("gemini-2.0-flash"));
+/// let results = extract_batch(paths, &ExtractionOptions::default(), &queue, Some(sender)).await;
+/// for (path, result) in results {
+///     if let Err(e) = result {
+///         eprintln!("{} failed: {:#}", path.display(), e);
+///     }
+/// }
+/// # }
+/// ```
+pub async fn extract_batch(
+    paths: Vec<PathBuf>,
+    options: &ExtractionOptions,
+    queue: &RequestQueue,
+    progress: Option<mpsc::Sender<ProgressEvent>>,
+) -> Vec<(PathBuf, Result<serde_json::Value>)> {
+    // Keeping more futures in flight than the queue admits would only make them wait
+    let concurrency = queue.max_concurrent_requests().max(1);
+
+    futures::stream::iter(paths)
+        .map(|path| {
+            let progress = &progress;
+            async move {
+                send_progress(progress, ProgressEvent::Started { path: path.clone() }).await;
+
+                // The document is sent base64-encoded, 4 bytes for every 3
+                let cost = fs::metadata(&path)
+                    .map(|metadata| estimate_token_cost((metadata.len() as usize).div_ceil(3) * 4))
+                    .unwrap_or(0);
+                let result = queue
+                    .execute_async_with_cost(cost, || {
+                        extract_data_from_path_with_usage(&path, options.clone())
+                    })
+                    .await;
+
+                if let (Some(manifest_path), Ok((value, _))) = (&options.manifest, &result) {
+                    if let Err(e) = record_in_manifest(manifest_path, &path, value) {
+                        warn!(
+                            path = %path.display(),
+                            error = %format!("{:#}", e),
+                            "Failed to record extraction in manifest"
+                        );
+                    }
+                }
+
+                let event = match &result {
+                    Ok((_, usage)) => ProgressEvent::Completed {
+                        path: path.clone(),
+                        tokens: usage.total_token_count,
+                    },
+                    Err(e) => ProgressEvent::Failed {
+                        path: path.clone(),
+                        error: format!("{:#}", e),
+                    },
+                };
+                send_progress(progress, event).await;
+
+                (path, result.map(|(value, _)| value))
+            }
+        })
+        .buffered(concurrency)
+        .collect()
+        .await
+}
+
+/// Extracts data from many documents, skipping those a previous run completed
+///
+/// Documents recorded in the manifest are returned from their output files
+/// without calling the API, and reported as [`ProgressEvent::Skipped`]. The rest
+/// go through [`extract_batch`] with `manifest_path` as the manifest, so running
+/// the same batch again after a crash only pays for unfinished documents. A
+/// recorded document whose output file is gone is extracted again.
+///
+/// # Arguments
+///
+/// * `paths` - The documents to extract
+/// * `manifest_path` - The JSONL manifest to read and append to (created if missing)
+/// * `options` - Prompt, model, and endpoint settings shared by every document
+/// * `queue` - The rate limiter the batch runs under
+/// * `progress` - Optional channel that receives a [`ProgressEvent`] per document
+///
+/// # Returns
+///
+/// * `Result<Vec<(PathBuf, Result<serde_json::Value>)>, anyhow::Error>` - Every
+///   outcome next to its path, in input order, or an error if the manifest
+///   cannot be read
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::{extract_batch_resume, ExtractionOptions};
+/// use hvertex::queue::{QueueConfig, RequestQueue};
+/// use std::path::{Path, PathBuf};
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let paths = vec![PathBuf::from("a.pdf"), PathBuf::from("b.pdf")];
+/// let queue = RequestQueue::new(QueueConfig::for_model("gemini-2.0-flash"));
+/// let results = extract_batch_resume(
+///     paths,
+///     Path::new("batch.jsonl"),
+///     &ExtractionOptions::default(),
+///     &queue,
+///     None,
+/// )
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn extract_batch_resume(
+    paths: Vec<PathBuf>,
+    manifest_path: &Path,
+    options: &ExtractionOptions,
+    queue: &RequestQueue,
+    progress: Option<mpsc::Sender<ProgressEvent>>,
+) -> Result<Vec<(PathBuf, Result<serde_json::Value>)>> {
+    let completed = read_manifest(manifest_path)?;
+
+    let mut results: Vec<Option<Result<serde_json::Value>>> = Vec::with_capacity(paths.len());
+    let mut pending = Vec::new();
+    for path in &paths {
+        let recorded = completed
+            .get(&manifest_key(path))
+            .and_then(|output| read_cached(output));
+        match recorded {
+            Some(value) => {
+                send_progress(&progress, ProgressEvent::Skipped { path: path.clone() }).await;
+                results.push(Some(Ok(value)));
+            }
+            None => {
+                pending.push(path.clone());
+                results.push(None);
+            }
+        }
+    }
+    info!(
+        skipped = paths.len() - pending.len(),
+        remaining = pending.len(),
+        "Resuming batch"
+    );
+
+    let options = ExtractionOptions {
+        manifest: Some(manifest_path.to_path_buf()),
+        ..options.clone()
+    };
+    let mut extracted = extract_batch(pending, &options, queue, progress)
+        .await
+        .into_iter();
+
+    Ok(paths
+        .into_iter()
+        .zip(results)
+        .map(|(path, result)| {
+            let result = result.unwrap_or_else(|| match extracted.next() {
+                Some((_, result)) => result,
+                None => Err(anyhow::anyhow!("No result for {}", path.display())),
+            });
+            (path, result)
+        })
+        .collect())
+}
+
+/// Sends a progress event if a channel was given, ignoring a closed receiver
+async fn send_progress(progress: &Option<mpsc::Sender<ProgressEvent>>, event: ProgressEvent) {
+    if let Some(sender) = progress {
+        let _ = sender.send(event).await;
+    }
+}
+
+/// Validates extracted data against a JSON Schema
+///
+/// Use this to reject or flag an extraction before persisting it, for example
+/// when a required field is missing or `accuracy_score` is out of range.
+///
+/// # Arguments
+///
+/// * `value` - The extracted data
+/// * `schema` - The JSON Schema it must satisfy
+///
+/// # Returns
+///
+/// * `Result<(), Vec<String>>` - Ok if valid, otherwise one message per violation
+///   (or a single message if the schema itself is invalid)
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::pdf::validate_extraction;
+/// use serde_json::json;
+///
+/// let schema = json!({
+///     "type": "object",
+///     "required": ["document_type", "contract_number", "accuracy_score"]
+/// });
+/// let data = json!({ "document_type": "insurance_contract" });
+///
+/// let violations = validate_extraction(&data, &schema).unwrap_err();
+/// assert_eq!(violations.len(), 2);
+/// ```
+pub fn validate_extraction(
+    value: &serde_json::Value,
+    schema: &serde_json::Value,
+) -> std::result::Result<(), Vec<String>> {
+    let compiled =
+        JSONSchema::compile(schema).map_err(|e| vec![format!("Invalid schema: {}", e)])?;
+
+    compiled.validate(value).map_err(|errors| {
+        errors
+            .map(|error| {
+                let path = error.instance_path.to_string();
+                if path.is_empty() {
+                    error.to_string()
+                } else {
+                    format!("{}: {}", path, error)
+                }
+            })
+            .collect()
+    })
+}
+
+/// Keys holding the accuracy score of a `{"value": ..., "accuracy": ...}` field
+const SCORE_KEYS: &[&str] = &[
+    "accuracy",
+    "accuracy_score",
+    "confidence",
+    "confidence_score",
+];
+
+/// Keys holding a map from sibling field names to their accuracy scores
+const SCORE_MAP_KEYS: &[&str] = &["accuracy_scores", "confidence_scores"];
+
+/// Removes extracted values whose accuracy score is below `min_score`
+///
+/// Scores are read from two shapes: a field that is an object holding `value`
+/// and `accuracy` (or `accuracy_score`, `confidence`, `confidence_score`), and
+/// an `accuracy_scores` (or `confidence_scores`) map scoring sibling fields by
+/// name. Scores above 1 are read as percentages, so 85 and "85%" mean 0.85.
+///
+/// Low-confidence fields are dropped from objects and arrays at any depth;
+/// fields without a score, and the score maps themselves, are kept.
+///
+/// # Arguments
+///
+/// * `value` - The extracted data
+/// * `min_score` - The lowest score kept, between 0 and 1
+///
+/// # Returns
+///
+/// * A copy of `value` without the low-confidence fields
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::pdf::filter_by_confidence;
+/// use serde_json::json;
+///
+/// let data = json!({
+///     "policy_number": { "value": "P-1", "accuracy": 0.95 },
+///     "premium": { "value": "1,200 EUR", "accuracy": 0.4 },
+///     "holder": "Jane Doe",
+///     "start_date": "2024-03-01",
+///     "accuracy_scores": { "holder": 0.9, "start_date": 0.3 }
+/// });
+///
+/// let filtered = filter_by_confidence(&data, 0.8);
+/// assert!(filtered.get("premium").is_none());
+/// assert!(filtered.get("start_date").is_none());
+/// assert_eq!(filtered["holder"], "Jane Doe");
+/// ```
+pub fn filter_by_confidence(value: &serde_json::Value, min_score: f64) -> serde_json::Value {
+    let confident = |score: Option<f64>| score.is_none_or(|score| score >= min_score);
+
+    match value {
+        serde_json::Value::Object(map) => {
+            let scores = SCORE_MAP_KEYS
+                .iter()
+                .find_map(|key| map.get(*key))
+                .and_then(serde_json::Value::as_object);
+            map.iter()
+                .filter(|(key, field)| {
+                    let score = scores
+                        .and_then(|scores| scores.get(key.as_str()))
+                        .and_then(parse_score)
+                        .or_else(|| field_score(field));
+                    confident(score)
+                })
+                .map(|(key, field)| (key.clone(), filter_by_confidence(field, min_score)))
+                .collect()
+        }
+        serde_json::Value::Array(items) => items
+            .iter()
+            .filter(|item| confident(field_score(item)))
+            .map(|item| filter_by_confidence(item, min_score))
+            .collect(),
+        other => other.clone(),
+    }
+}
+
+/// Returns the score of a `{"value": ..., "accuracy": ...}` field
+fn field_score(field: &serde_json::Value) -> Option<f64> {
+    let field = field
+        .as_object()
+        .filter(|field| field.contains_key("value"))?;
+    SCORE_KEYS
+        .iter()
+        .find_map(|key| field.get(*key))
+        .and_then(parse_score)
+}
+
+/// Reads a score as a fraction, converting percentages such as 85 or "85%"
+fn parse_score(score: &serde_json::Value) -> Option<f64> {
+    let score = match score {
+        serde_json::Value::Number(number) => number.as_f64()?,
+        serde_json::Value::String(text) => text.trim().trim_end_matches('%').parse().ok()?,
+        _ => return None,
+    };
+    Some(if score > 1.0 { score / 100.0 } else { score })
+}
+
+/// Which code blocks [`extract_json_from_raw_text_with`] returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlockSelection {
+    /// The largest block that parses as JSON
+    #[default]
+    Largest,
+    /// Every block that parses as JSON, in order, as a JSON array
+    All,
+}
+
+/// Extracts and parses JSON data from raw text that contains Markdown code blocks
+///
+/// This function is designed to handle responses from the Vertex AI API that
+/// may return JSON data wrapped in Markdown code blocks (```json ... ```).
+/// When the text contains several blocks, the largest one that parses as JSON
+/// is returned; use [`extract_json_from_raw_text_with`] to collect them all.
+///
+/// # Arguments
+///
+/// * `raw_text` - A string slice containing the raw text with potential JSON data in code blocks
+///
+/// # Returns
+///
+/// * `Result<serde_json::Value, anyhow::Error>` - The parsed JSON value or an error
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::pdf::extract_json_from_raw_text;
+///
+/// let text = "Here is the data:\n```json\n{\"policy\": \"P-1\"}\n```";
+/// let value = extract_json_from_raw_text(text)?;
+/// assert_eq!(value["policy"], "P-1");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn extract_json_from_raw_text(raw_text: &str) -> Result<serde_json::Value> {
+    extract_json_from_raw_text_with(raw_text, BlockSelection::Largest, false)
+}
+
+/// Extracts JSON from raw text, choosing how multiple code blocks are combined
+///
+/// Blocks that are not valid JSON (e.g., a prose summary) are skipped. Text with
+/// no code blocks is parsed as JSON directly.
+///
+/// When `lenient` is set, text that fails to parse is repaired with
+/// [`repair_json`] and parsed again, and a warning is logged if the repair
+/// succeeds. Text that is still invalid after the repair is an error as usual.
+///
+/// # Arguments
+///
+/// * `raw_text` - The raw text with potential JSON data in code blocks
+/// * `selection` - Whether to return the largest valid block or all of them
+/// * `lenient` - Whether to repair trailing commas and unquoted keys
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::pdf::{extract_json_from_raw_text_with, BlockSelection};
+///
+/// let text = "```json\n{\"items\": [1, 2,],}\n```";
+/// assert!(extract_json_from_raw_text_with(text, BlockSelection::Largest, false).is_err());
+/// let value = extract_json_from_raw_text_with(text, BlockSelection::Largest, true)?;
+/// assert_eq!(value["items"][1], 2);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn extract_json_from_raw_text_with(
+    raw_text: &str,
+    selection: BlockSelection,
+    lenient: bool,
+) -> Result<serde_json::Value> {
+    // First, check if the input is a JSON object with a "raw_text" field
+    if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(raw_text) {
+        if let Some(inner_text) = parsed.get("raw_text").and_then(|v| v.as_str()) {
+            // If we have a "raw_text" field, use its value as our raw text
+            return extract_json_from_raw_text_with(inner_text, selection, lenient);
+        }
+    }
+
+    let blocks = code_blocks(raw_text)?;
+    if blocks.is_empty() {
+        // If no code blocks were found, try to parse the entire text as JSON
+        return parse_json(raw_text, lenient)
+            .context("Failed to parse text as JSON and no code blocks were found");
+    }
+
+    let mut parsed: Vec<(usize, serde_json::Value)> = blocks
+        .iter()
+        .filter_map(|block| {
+            parse_json(block, lenient)
+                .ok()
+                .map(|value| (block.len(), value))
+        })
+        .collect();
+    if parsed.is_empty() {
         return Err(anyhow::anyhow!(
-            "API request failed with status code {}: {}",
-            status,
-            error_text
+            "Found {} code block(s) but none contained valid JSON",
+            blocks.len()
         ));
     }
 
-    // Parse the response
-    let response_json: Value = response
-        .json()
-        .await
-        .context("Failed to parse API response as JSON")?;
-
-    // Extract the generated text and parse it as JSON
-    if let Some(candidates) = response_json["candidates"].as_array() {
-        if let Some(content) = candidates
-            .first()
-            .and_then(|c| c["content"]["parts"].as_array())
-            .and_then(|parts| parts.first())
-            .and_then(|p| p["text"].as_str())
-        {
-            // Try to parse the response text as JSON
-            match serde_json::from_str::<Value>(content) {
-                Ok(json_data) => {
-                    return Ok(json_data);
+    Ok(match selection {
+        BlockSelection::All => {
+            serde_json::Value::Array(parsed.into_iter().map(|(_, value)| value).collect())
+        }
+        BlockSelection::Largest => {
+            // Keep the first block when sizes tie
+            let index = parsed
+                .iter()
+                .enumerate()
+                .rev()
+                .max_by_key(|(_, (len, _))| *len)
+                .map(|(index, _)| index)
+                .unwrap_or(0);
+            parsed.swap_remove(index).1
+        }
+    })
+}
+
+/// Parses JSON, retrying once on the repaired text when `lenient` is set
+///
+/// Returns the original parse error if the repaired text is invalid too.
+fn parse_json(text: &str, lenient: bool) -> Result<serde_json::Value> {
+    let error = match serde_json::from_str(text) {
+        Ok(value) => return Ok(value),
+        Err(error) if !lenient => return Err(error.into()),
+        Err(error) => error,
+    };
+
+    let repaired = repair_json(text);
+    match serde_json::from_str(&repaired) {
+        Ok(value) => {
+            warn!(error = %error, "Parsed JSON only after repairing it");
+            Ok(value)
+        }
+        Err(_) => Err(error.into()),
+    }
+}
+
+/// Repairs common defects in nearly-valid JSON generated by a model
+///
+/// Removes trailing commas before a closing `}` or `]` and quotes bare object
+/// keys such as `{name: "A"}`. Text inside strings is left untouched, and other
+/// problems are not repaired.
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::pdf::repair_json;
+///
+/// assert_eq!(repair_json("{name: \"a, b\", tags: [1,],}"), "{\"name\": \"a, b\", \"tags\": [1]}");
+/// ```
+pub fn repair_json(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let next_significant = |from: usize| chars[from..].iter().copied().find(|c| !c.is_whitespace());
+
+    let mut repaired = String::with_capacity(text.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if in_string {
+            repaired.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                repaired.push(c);
+            }
+            // Drop a comma that is followed only by whitespace and a closing bracket
+            ',' if matches!(next_significant(i + 1), Some('}' | ']')) => {}
+            c if (c.is_alphabetic() || c == '_')
+                && matches!(repaired.trim_end().chars().last(), Some('{' | ',')) =>
+            {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
                 }
-                Err(e) => {
-                    // If parsing as JSON fails, return the raw text as a JSON string
-                    println!(
-                        "Warning: Could not parse response as JSON ({}). Returning raw text.",
-                        e
-                    );
-                    return Ok(json!({ "raw_text": content }));
+                let word: String = chars[start..i].iter().collect();
+                if next_significant(i) == Some(':') {
+                    repaired.push('"');
+                    repaired.push_str(&word);
+                    repaired.push('"');
+                } else {
+                    repaired.push_str(&word);
                 }
+                continue;
             }
+            _ => repaired.push(c),
         }
+        i += 1;
+    }
+    repaired
+}
+
+/// Returns the contents of every Markdown code block in the text
+///
+/// Fences must start a line, so backticks inside a JSON string do not end the
+/// block. Single-line blocks such as ```{"a": 1}``` are found as a fallback.
+fn code_blocks(text: &str) -> Result<Vec<String>> {
+    let mut blocks = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        match current.as_mut() {
+            None if trimmed.starts_with("```") && !trimmed[3..].contains("```") => {
+                current = Some(Vec::new());
+            }
+            Some(_) if trimmed == "```" => {
+                if let Some(lines) = current.take() {
+                    blocks.push(lines.join("\n"));
+                }
+            }
+            Some(lines) => lines.push(line),
+            None => {}
+        }
+    }
+
+    if blocks.is_empty() {
+        let re =
+            Regex::new(r"```(?:json)?\s*([\s\S]*?)\s*```").context("Failed to compile regex")?;
+        blocks = re
+            .captures_iter(text)
+            .filter_map(|captures| captures.get(1))
+            .map(|block| block.as_str().to_string())
+            .collect();
+    }
+
+    Ok(blocks)
+}
+
+/// Maps file extensions that cannot be detected from magic bytes to a MIME type
+fn mime_type_from_extension(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    match extension.as_str() {
+        "docx" => Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Extracts data from a PDF using the settings in a `Config`
+///
+/// Uses the configured project, region, and model, along with the prompt,
+/// system instruction, and service account impersonation overrides when set.
+///
+/// # Arguments
+///
+/// * `pdf_base64` - The base64-encoded PDF data
+/// * `config` - The configuration to use (an empty `project_id` falls back to `VERTEX_AI_PROJECT_ID`)
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::{pdf::extract_data_from_pdf_with_config, Config};
+///
+/// # async fn run(pdf_base64: &str) -> anyhow::Result<()> {
+/// let config = Config {
+///     prompt: Some("Extract the policy holder and premium as JSON".to_string()),
+///     ..Config::default()
+/// };
+/// let data = extract_data_from_pdf_with_config(pdf_base64, &config).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn extract_data_from_pdf_with_config(
+    pdf_base64: &str,
+    config: &Config,
+) -> Result<serde_json::Value> {
+    validate_base64(pdf_base64)?;
+
+    let project_id = (!config.project_id.is_empty()).then(|| config.project_id.clone());
+    let endpoint = ModelEndpoint::resolve(project_id, Some(&config.region), Some(&config.model))?
+        .with_impersonation(config.impersonate.clone())
+        .with_quota_project(config.quota_project.clone())
+        .with_endpoint_override(config.endpoint_override.as_deref())?;
+
+    let request = VertexAIRequest::new_pdf_extraction(
+        pdf_base64,
+        config.extraction_prompt(),
+        Some(config.extraction_system_instruction()),
+    );
+
+    extract_data_at_endpoint(&request, &endpoint, None).await
+}
+
+/// Extracts data from a PDF stored in Google Cloud Storage
+///
+/// Unlike [`extract_data_from_pdf_v2`], the PDF is not downloaded or base64-encoded;
+/// the request references the object by its `gs://` URI and Vertex AI reads it directly.
+///
+/// # Arguments
+///
+/// * `gs_uri` - The Cloud Storage URI of the PDF (e.g., "gs://bucket/file.pdf")
+/// * `prompt` - Optional extraction prompt (uses default if None)
+/// * `system_instruction` - Optional system instruction (uses default if None)
+/// * `project_id` - Optional project ID (falls back to `VERTEX_AI_PROJECT_ID`)
+/// * `location_id` - Optional location (defaults to "us-central1")
+/// * `model_id` - Optional model (defaults to "gemini-2.0-flash-exp")
+/// * `timeout` - Optional request timeout (defaults to 120 seconds)
+///
+/// # Returns
+///
+/// * `Result<serde_json::Value, anyhow::Error>` - The extracted data or error
+pub async fn extract_data_from_gcs(
+    gs_uri: &str,
+    prompt: Option<&str>,
+    system_instruction: Option<&str>,
+    project_id: Option<String>,
+    location_id: Option<&str>,
+    model_id: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<serde_json::Value> {
+    if !gs_uri.starts_with("gs://") {
+        return Err(anyhow::anyhow!(
+            "Invalid Cloud Storage URI '{}': expected a gs:// URI",
+            gs_uri
+        ));
     }
 
-    // If we couldn't extract the response, return an error
-    Err(anyhow::anyhow!(
-        "Failed to extract data from the API response"
-    ))
+    let prompt_text = prompt.unwrap_or(DEFAULT_EXTRACTION_PROMPT);
+    let request =
+        VertexAIRequest::new_pdf_extraction_from_gcs(gs_uri, prompt_text, system_instruction);
+
+    extract_data_with_request(&request, project_id, location_id, model_id, timeout).await
+}
+
+/// Sends a prepared extraction request to the generateContent endpoint and parses the result
+///
+/// Use this to send a customized request, for example one built with
+/// [`VertexAIRequest::with_json_schema`]. When the request asks for an
+/// "application/json" response, the generated text must be valid JSON and is
+/// returned as-is; otherwise text that is not valid JSON is returned as
+/// `{"raw_text": ...}`.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::extract_data_with_request;
+/// use hvertex::VertexAIRequest;
+/// use serde_json::json;
+///
+/// # async fn run(pdf_base64: &str) -> anyhow::Result<()> {
+/// let schema = json!({
+///     "type": "OBJECT",
+///     "properties": { "policy_number": { "type": "STRING" } }
+/// });
+/// let request = VertexAIRequest::new_pdf_extraction(pdf_base64, "Extract the policy", None)
+///     .with_json_schema(schema);
+/// let data = extract_data_with_request(&request, None, None, None, None).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn extract_data_with_request(
+    request: &VertexAIRequest,
+    project_id: Option<String>,
+    location_id: Option<&str>,
+    model_id: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<serde_json::Value> {
+    // Get the project ID, location ID, and model ID with default values
+    let endpoint = ModelEndpoint::resolve(project_id, location_id, model_id)?;
+
+    extract_data_at_endpoint(request, &endpoint, timeout).await
+}
+
+/// Sends an extraction request to a resolved endpoint and parses the result
+async fn extract_data_at_endpoint(
+    request: &VertexAIRequest,
+    endpoint: &ModelEndpoint,
+    timeout: Option<Duration>,
+) -> Result<serde_json::Value> {
+    let (value, _) = extract_data_at_endpoint_with_usage(request, endpoint, timeout, 0).await?;
+    Ok(value)
+}
+
+/// Sends an extraction request to a resolved endpoint, returning the token usage too
+///
+/// A response truncated at `maxOutputTokens` is continued up to
+/// `max_continuations` times.
+async fn extract_data_at_endpoint_with_usage(
+    request: &VertexAIRequest,
+    endpoint: &ModelEndpoint,
+    timeout: Option<Duration>,
+    max_continuations: u32,
+) -> Result<(serde_json::Value, UsageMetadata)> {
+    let transport = HttpTransport::for_endpoint(endpoint, timeout);
+    match extract_with_continuations(&transport, request, endpoint, max_continuations).await {
+        Err(e) => Err(explain_not_found(e, &transport, endpoint).await),
+        result => result,
+    }
+}
+
+/// Sends an extraction request through a custom transport and parses the result
+///
+/// Parses the response exactly like [`extract_data_with_request`], which makes
+/// it possible to test extraction offline with a `MockTransport`.
+///
+/// # Returns
+///
+/// * `Result<(serde_json::Value, UsageMetadata), anyhow::Error>` - The extracted
+///   data and the token usage reported for the request
+pub async fn extract_data_with_transport<T: Transport>(
+    transport: &T,
+    request: &VertexAIRequest,
+    endpoint: &ModelEndpoint,
+) -> Result<(serde_json::Value, UsageMetadata)> {
+    extract_with_continuations(transport, request, endpoint, 0).await
+}
+
+/// Sends an extraction request, continuing a response truncated at
+/// `maxOutputTokens` up to `max_continuations` times
+///
+/// Each continuation resends the conversation with the output so far as a
+/// model turn and a request to continue, and the pieces are concatenated before
+/// parsing. Usage is summed across every request.
+///
+/// When several candidates were requested, a candidate that finished with valid
+/// JSON is used instead of continuing the first one, and continuations ask for
+/// a single candidate.
+async fn extract_with_continuations<T: Transport>(
+    transport: &T,
+    request: &VertexAIRequest,
+    endpoint: &ModelEndpoint,
+    max_continuations: u32,
+) -> Result<(serde_json::Value, UsageMetadata)> {
+    info!(
+        project_id = %endpoint.project_id,
+        model = %endpoint.model_id,
+        "Extracting data from PDF using Vertex AI"
+    );
+
+    let mut conversation = request.clone();
+    let mut text = String::new();
+    let mut usage = UsageMetadata::default();
+    let mut continuations = 0;
+    loop {
+        // Make the API request
+        let response = generate_content_with(transport, &conversation, endpoint).await?;
+        if let Some(response_usage) = &response.usage_metadata {
+            usage += response_usage;
+        }
+
+        // A sibling candidate may have finished even though the first was cut off
+        if continuations == 0 {
+            if let Some(json_text) = json_candidate_text(&response) {
+                text = json_text;
+                break;
+            }
+        }
+
+        let candidate = response.candidates.first();
+        let truncated = candidate.and_then(|c| c.finish_reason.as_deref()) == Some("MAX_TOKENS");
+        if let Some(candidate) =
+            candidate.filter(|_| truncated && continuations < max_continuations)
+        {
+            continuations += 1;
+            warn!(
+                continuations,
+                "Output hit maxOutputTokens, continuing generation"
+            );
+            let piece = candidate.text();
+            text.push_str(&piece);
+            conversation.push_model_text(piece);
+            conversation.push_user_text(CONTINUE_PROMPT);
+            // Only the first candidate is continued, so don't pay for the others
+            conversation.generation_config.candidate_count = None;
+            continue;
+        }
+
+        // Fails with VertexError::FinishReason if the output is still truncated
+        if continuations == 0 {
+            text = best_candidate_text(&response)?;
+        } else {
+            text.push_str(&response.text()?);
+        }
+        break;
+    }
+
+    // Parse the generated text as JSON
+    let value = if request.expects_json() {
+        parse_strict_json(&text)?
+    } else {
+        parse_generated_json(&text)
+    };
+    Ok((value, usage))
+}
+
+/// Returns the text of the first candidate whose output is valid JSON
+///
+/// When several candidates were requested, a later one may be clean JSON when
+/// the first is malformed. Falls back to the first candidate's text, failing
+/// like `GenerateContentResponse::text` if it was blocked or truncated.
+fn best_candidate_text(response: &GenerateContentResponse) -> Result<String> {
+    match json_candidate_text(response) {
+        Some(text) => Ok(text),
+        None => response.text(),
+    }
+}
+
+/// Returns the text of the first finished candidate that is valid JSON, when
+/// several candidates were returned
+fn json_candidate_text(response: &GenerateContentResponse) -> Option<String> {
+    if response.candidates.len() < 2 {
+        return None;
+    }
+    response
+        .candidate_texts()
+        .ok()?
+        .into_iter()
+        .find(|text| serde_json::from_str::<serde_json::Value>(text).is_ok())
+}
+
+/// Extracts data from a PDF, streaming the generated text as it arrives
+///
+/// Calls `on_delta` with each text fragment from the `streamGenerateContent`
+/// endpoint, so a UI can render output live instead of waiting for the whole
+/// response. Once the stream ends, the accumulated text is parsed like
+/// [`extract_data_from_pdf_v2`].
+///
+/// # Arguments
+///
+/// * `pdf_base64` - The base64-encoded PDF data
+/// * `prompt` - Optional extraction prompt (uses default if None)
+/// * `system_instruction` - Optional system instruction (uses default if None)
+/// * `project_id` - Optional project ID (falls back to `VERTEX_AI_PROJECT_ID`)
+/// * `location_id` - Optional location (defaults to "us-central1")
+/// * `model_id` - Optional model (defaults to "gemini-2.0-flash-exp")
+/// * `on_delta` - Callback invoked with each text delta
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::extract_data_from_pdf_stream_async;
+/// use std::io::Write;
+///
+/// # async fn run(pdf_base64: &str) -> anyhow::Result<()> {
+/// let data = extract_data_from_pdf_stream_async(pdf_base64, None, None, None, None, None, |delta| {
+///     print!("{}", delta);
+///     let _ = std::io::stdout().flush();
+/// })
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn extract_data_from_pdf_stream_async<F>(
+    pdf_base64: &str,
+    prompt: Option<&str>,
+    system_instruction: Option<&str>,
+    project_id: Option<String>,
+    location_id: Option<&str>,
+    model_id: Option<&str>,
+    mut on_delta: F,
+) -> Result<serde_json::Value>
+where
+    F: FnMut(&str),
+{
+    validate_base64(pdf_base64)?;
+
+    let prompt_text = prompt.unwrap_or(DEFAULT_EXTRACTION_PROMPT);
+    let request = VertexAIRequest::new_pdf_extraction(pdf_base64, prompt_text, system_instruction);
+
+    let endpoint = ModelEndpoint::resolve(project_id, location_id, model_id)?;
+    let text = stream_generated_text(&request, &endpoint, None, |delta| {
+        on_delta(delta);
+        Ok(())
+    })
+    .await?;
+
+    Ok(parse_generated_json(&text))
+}
+
+/// Extracts data from a PDF, writing the generated text to `writer` as it arrives
+///
+/// Multi-byte characters are never split across writes (see [`StreamWriter`]),
+/// so the writer receives valid UTF-8 even when written to mid-stream.
+///
+/// # Arguments
+///
+/// * `pdf_base64` - The base64-encoded PDF data
+/// * `options` - Prompt, model, and endpoint settings
+/// * `writer` - Where to write the generated text
+/// * `flush_each_chunk` - Whether to flush `writer` after every streamed chunk
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::{extract_data_from_pdf_stream, ExtractionOptions};
+///
+/// # async fn run(pdf_base64: &str) -> anyhow::Result<()> {
+/// let file = std::fs::File::create("output.txt")?;
+/// let data = extract_data_from_pdf_stream(pdf_base64, &ExtractionOptions::default(), file, true)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn extract_data_from_pdf_stream<W: Write>(
+    pdf_base64: &str,
+    options: &ExtractionOptions,
+    writer: W,
+    flush_each_chunk: bool,
+) -> Result<serde_json::Value> {
+    validate_base64(pdf_base64)?;
+
+    let prompt_text = options
+        .prompt
+        .as_deref()
+        .unwrap_or(DEFAULT_EXTRACTION_PROMPT);
+    let request = VertexAIRequest::new_pdf_extraction(
+        pdf_base64,
+        prompt_text,
+        options.system_instruction_with_language()?.as_deref(),
+    );
+
+    let endpoint = options.endpoint()?;
+
+    let mut writer = StreamWriter::new(writer, flush_each_chunk);
+    let text = cancellable(
+        options.cancellation.clone(),
+        stream_generated_text(&request, &endpoint, options.timeout, |delta| {
+            writer
+                .write_chunk(delta.as_bytes())
+                .map_err(VertexError::Io)
+                .context("Failed to write streamed text")
+        }),
+    )
+    .await?;
+    writer
+        .finish()
+        .map_err(VertexError::Io)
+        .context("Failed to write streamed text")?;
+
+    Ok(parse_generated_json(&text))
+}
+
+/// Reassembles streamed text deltas into the final JSON object
+///
+/// Deltas split JSON (and its Markdown code fences) at arbitrary points, so
+/// nothing is parsed until the stream ends; [`StreamingJsonCollector::finish`]
+/// then parses the complete text with [`extract_json_from_raw_text`].
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::pdf::StreamingJsonCollector;
+///
+/// let mut collector = StreamingJsonCollector::new();
+/// for delta in ["```json\n{\"poli", "cy\": \"P-1\"}", "\n```"] {
+///     collector.push(delta);
+/// }
+/// assert_eq!(collector.finish()?["policy"], "P-1");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StreamingJsonCollector {
+    text: String,
+}
+
+impl StreamingJsonCollector {
+    /// Creates an empty collector
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a text delta
+    pub fn push(&mut self, delta: &str) {
+        self.text.push_str(delta);
+    }
+
+    /// Returns the text received so far
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Parses the complete text as JSON, unwrapping Markdown code blocks
+    pub fn finish(self) -> Result<serde_json::Value> {
+        extract_json_from_raw_text(&self.text)
+    }
+}
+
+/// Extracts data from a PDF over a streaming connection, returning the parsed result
+///
+/// Streaming keeps the connection active while a long response is generated,
+/// avoiding the timeouts a single long request can hit, but the caller still
+/// gets one parsed object like [`extract_data_from_pdf_with_options`].
+///
+/// # Arguments
+///
+/// * `pdf_base64` - The base64-encoded PDF data
+/// * `options` - Prompt, model, and endpoint settings
+///
+/// # Returns
+///
+/// * `Result<serde_json::Value, anyhow::Error>` - The extracted data, or an
+///   error if the complete response is not valid JSON
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::pdf::{extract_data_from_pdf_stream_collect, ExtractionOptions};
+///
+/// # async fn run(pdf_base64: &str) -> anyhow::Result<()> {
+/// let data = extract_data_from_pdf_stream_collect(pdf_base64, &ExtractionOptions::default()).await?;
+/// println!("{}", serde_json::to_string_pretty(&data)?);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn extract_data_from_pdf_stream_collect(
+    pdf_base64: &str,
+    options: &ExtractionOptions,
+) -> Result<serde_json::Value> {
+    validate_base64(pdf_base64)?;
+
+    let request = VertexAIRequest::new_pdf_extraction(
+        pdf_base64,
+        options
+            .prompt
+            .as_deref()
+            .unwrap_or(DEFAULT_EXTRACTION_PROMPT),
+        options.system_instruction_with_language()?.as_deref(),
+    )
+    .with_part_order(options.part_order);
+    let endpoint = options.endpoint()?;
+
+    let mut collector = StreamingJsonCollector::new();
+    cancellable(
+        options.cancellation.clone(),
+        stream_generated_text(&request, &endpoint, options.timeout, |delta| {
+            collector.push(delta);
+            Ok(())
+        }),
+    )
+    .await?;
+
+    collector.finish()
+}
+
+/// Streams a request from `streamGenerateContent`, passing each text delta to
+/// `on_delta` and returning the accumulated text
+async fn stream_generated_text<F>(
+    request: &VertexAIRequest,
+    endpoint: &ModelEndpoint,
+    timeout: Option<Duration>,
+    mut on_delta: F,
+) -> Result<String>
+where
+    F: FnMut(&str) -> Result<()>,
+{
+    let access_token = endpoint.access_token()?;
+    let client = build_http_client(timeout)?;
+    let api_url = format!("{}?alt=sse", endpoint.url("streamGenerateContent"));
+    let headers = endpoint.request_headers(&access_token)?;
+
+    let response = send_with_retry(
+        &client,
+        |client| client.post(&api_url).headers(headers.clone()).json(request),
+        RetryPolicy::default(),
+    )
+    .await
+    .map_err(|e| transport_error(e, timeout, "Failed to make Vertex AI API request"))?;
+    let response = error_for_status(response).await?;
+
+    // A mid-stream error keeps the text received so far in VertexError::StreamInterrupted
+    let mut collector = StreamTextCollector::new();
+    let mut bytes = response.bytes_stream();
+    while let Some(bytes) = bytes.next().await {
+        let bytes =
+            bytes.map_err(|e| transport_error(e, timeout, "Failed to read response stream"))?;
+        collector.push(&bytes, &mut on_delta)?;
+    }
+
+    collector.finish(&mut on_delta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::QueueConfig;
+    use crate::transport::MockTransport;
+    use futures::TryStreamExt;
+
+    #[tokio::test]
+    async fn test_extract_from_path_rejects_missing_and_empty_files() {
+        let missing = std::env::temp_dir().join("hvertex-missing-file.pdf");
+        let error = extract_data_from_path(&missing, &ExtractionOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<VertexError>(),
+            Some(VertexError::FileNotFound(_))
+        ));
+
+        let empty = std::env::temp_dir().join("hvertex-empty-file.pdf");
+        fs::write(&empty, b"").unwrap();
+        let error = extract_data_from_path(&empty, &ExtractionOptions::default())
+            .await
+            .unwrap_err();
+        fs::remove_file(&empty).unwrap();
+        assert!(matches!(
+            error.downcast_ref::<VertexError>(),
+            Some(VertexError::EmptyFile(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_extraction_returns_cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let options = ExtractionOptions {
+            project_id: Some("my-project".to_string()),
+            cancellation: Some(token),
+            ..ExtractionOptions::default()
+        };
+
+        let error = extract_data_from_pdf_with_options("JVBERi0xLjQ=", &options)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<VertexError>(),
+            Some(VertexError::Cancelled)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_extract_from_url_reports_download_failures() {
+        let error = extract_data_from_url(
+            "http://127.0.0.1:1/doc.pdf?X-Goog-Signature=secret",
+            &ExtractionOptions::default(),
+        )
+        .await
+        .unwrap_err();
+        match error.downcast_ref::<VertexError>() {
+            Some(VertexError::Download { url, reason }) => {
+                assert_eq!(url, "http://127.0.0.1:1/doc.pdf");
+                assert!(!reason.contains("secret"));
+            }
+            other => panic!("expected a download error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extract_batch_keeps_paths_and_order() {
+        let paths: Vec<PathBuf> = (0..3)
+            .map(|i| std::env::temp_dir().join(format!("hvertex-batch-missing-{}.pdf", i)))
+            .collect();
+
+        let queue = RequestQueue::new(QueueConfig {
+            max_concurrent_requests: 2,
+            ..QueueConfig::default()
+        });
+        let (sender, mut receiver) = mpsc::channel(16);
+        let results = extract_batch(
+            paths.clone(),
+            &ExtractionOptions::default(),
+            &queue,
+            Some(sender),
+        )
+        .await;
+
+        assert_eq!(results.len(), 3);
+        for ((path, result), expected) in results.iter().zip(&paths) {
+            assert_eq!(path, expected);
+            assert!(result.is_err());
+        }
+
+        let mut started = 0;
+        let mut failed = 0;
+        while let Some(event) = receiver.recv().await {
+            match event {
+                ProgressEvent::Started { .. } => started += 1,
+                ProgressEvent::Failed { .. } => failed += 1,
+                ProgressEvent::Completed { .. } | ProgressEvent::Skipped { .. } => {
+                    panic!("missing file completed")
+                }
+            }
+        }
+        assert_eq!((started, failed), (3, 3));
+    }
+
+    #[tokio::test]
+    async fn test_extract_batch_resume_skips_recorded_documents() {
+        let dir = std::env::temp_dir().join(format!("hvertex-manifest-{}", std::process::id()));
+        let manifest_path = dir.join("batch.jsonl");
+        let done = dir.join("done.pdf");
+        let missing = dir.join("missing.pdf");
+        let value = serde_json::json!({ "contract_number": "C-1" });
+        record_in_manifest(&manifest_path, &done, &value).unwrap();
+        // A line cut short by a crash is ignored
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&manifest_path)
+            .unwrap()
+            .write_all(b"{\"path\":")
+            .unwrap();
+        assert_eq!(read_manifest(&manifest_path).unwrap().len(), 1);
+
+        let queue = RequestQueue::new(QueueConfig::default());
+        let (sender, mut receiver) = mpsc::channel(16);
+        let results = extract_batch_resume(
+            vec![done.clone(), missing.clone()],
+            &manifest_path,
+            &ExtractionOptions::default(),
+            &queue,
+            Some(sender),
+        )
+        .await
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(results[0].0, done);
+        assert_eq!(results[0].1.as_ref().unwrap(), &value);
+        assert_eq!(results[1].0, missing);
+        assert!(results[1].1.is_err());
+        assert!(matches!(
+            receiver.recv().await,
+            Some(ProgressEvent::Skipped { path }) if path == done
+        ));
+    }
+
+    fn test_endpoint() -> ModelEndpoint {
+        ModelEndpoint::resolve(Some("test-project".to_string()), None, None).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_extract_with_mock_transport() {
+        let transport = MockTransport::new();
+        transport.push_response(serde_json::json!({
+            "candidates": [{
+                "content": { "parts": [{ "text": "{\"contract_number\": \"C-42\"}" }] },
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": { "promptTokenCount": 100, "totalTokenCount": 120 }
+        }));
+        transport.push_response(serde_json::json!({
+            "candidates": [{
+                "content": { "parts": [{ "text": "Sorry, no JSON here" }] },
+                "finishReason": "STOP"
+            }]
+        }));
+
+        let request = VertexAIRequest::new_pdf_extraction("AAAA", "extract", None);
+        let (value, usage) = extract_data_with_transport(&transport, &request, &test_endpoint())
+            .await
+            .unwrap();
+        assert_eq!(value["contract_number"], "C-42");
+        assert_eq!(usage.total_token_count, 120);
+
+        let (value, _) = extract_data_with_transport(&transport, &request, &test_endpoint())
+            .await
+            .unwrap();
+        assert_eq!(value["raw_text"], "Sorry, no JSON here");
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0]
+            .0
+            .ends_with("/models/gemini-2.0-flash-exp:generateContent"));
+        assert_eq!(
+            requests[0].1["contents"][0]["parts"][0]["inline_data"]["data"],
+            "AAAA"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract_picks_first_json_candidate() {
+        let transport = MockTransport::new();
+        transport.push_response(serde_json::json!({
+            "candidates": [
+                { "content": { "parts": [{ "text": "{\"total\": 12," }] }, "finishReason": "STOP" },
+                { "content": { "parts": [{ "text": "{\"total\": 13}" }] }, "finishReason": "MAX_TOKENS" },
+                { "content": { "parts": [{ "text": "{\"total\": 14}" }] }, "finishReason": "STOP" }
+            ]
+        }));
+
+        let options = ExtractionOptions::default().candidate_count(3);
+        let request = options
+            .apply_candidate_count(VertexAIRequest::new_pdf_extraction("AAAA", "extract", None));
+        let (value, _) = extract_data_with_transport(&transport, &request, &test_endpoint())
+            .await
+            .unwrap();
+        assert_eq!(value["total"], 14);
+        assert_eq!(
+            transport.requests()[0].1["generation_config"]["candidate_count"],
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract_continuation_with_several_candidates() {
+        let transport = MockTransport::new();
+        let request = ExtractionOptions::default()
+            .candidate_count(2)
+            .apply_candidate_count(VertexAIRequest::new_pdf_extraction("AAAA", "extract", None));
+
+        // A finished sibling is used instead of continuing the first candidate
+        transport.push_response(serde_json::json!({
+            "candidates": [
+                { "content": { "parts": [{ "text": "{\"total\": " }] }, "finishReason": "MAX_TOKENS" },
+                { "content": { "parts": [{ "text": "{\"total\": 14}" }] }, "finishReason": "STOP" }
+            ]
+        }));
+        let (value, _) = extract_with_continuations(&transport, &request, &test_endpoint(), 3)
+            .await
+            .unwrap();
+        assert_eq!(value["total"], 14);
+        assert_eq!(transport.requests().len(), 1);
+
+        // Otherwise the first candidate is continued, asking for one candidate
+        transport.push_response(serde_json::json!({
+            "candidates": [
+                { "content": { "parts": [{ "text": "{\"total\": " }] }, "finishReason": "MAX_TOKENS" },
+                { "content": { "parts": [{ "text": "{\"tot" }] }, "finishReason": "MAX_TOKENS" }
+            ]
+        }));
+        transport.push_response(serde_json::json!({
+            "candidates": [{ "content": { "parts": [{ "text": "15}" }] }, "finishReason": "STOP" }]
+        }));
+        let (value, _) = extract_with_continuations(&transport, &request, &test_endpoint(), 3)
+            .await
+            .unwrap();
+        assert_eq!(value["total"], 15);
+        let requests = transport.requests();
+        assert_eq!(requests[1].1["generation_config"]["candidate_count"], 2);
+        assert!(requests[2].1["generation_config"]
+            .get("candidate_count")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_extract_continues_truncated_output() {
+        let truncated = |text: &str| {
+            serde_json::json!({
+                "candidates": [{
+                    "content": { "parts": [{ "text": text }] },
+                    "finishReason": "MAX_TOKENS"
+                }],
+                "usageMetadata": { "totalTokenCount": 10 }
+            })
+        };
+        let transport = MockTransport::new();
+        transport.push_response(truncated("{\"items\": [1, "));
+        transport.push_response(serde_json::json!({
+            "candidates": [{
+                "content": { "parts": [{ "text": "2]}" }] },
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": { "totalTokenCount": 5 }
+        }));
+
+        let request = VertexAIRequest::new_pdf_extraction("AAAA", "extract", None);
+        let (value, usage) = extract_with_continuations(&transport, &request, &test_endpoint(), 3)
+            .await
+            .unwrap();
+        assert_eq!(value["items"][1], 2);
+        assert_eq!(usage.total_token_count, 15);
+
+        // The second request carries the partial output and a request to continue
+        let contents = &transport.requests()[1].1["contents"];
+        assert_eq!(contents[1]["role"], "model");
+        assert_eq!(contents[1]["parts"][0]["text"], "{\"items\": [1, ");
+        assert_eq!(contents[2]["parts"][0]["text"], CONTINUE_PROMPT);
+
+        // Without continuations the truncation is an error
+        transport.push_response(truncated("{\"items\": ["));
+        let error = extract_with_continuations(&transport, &request, &test_endpoint(), 0)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<VertexError>(),
+            Some(VertexError::FinishReason { reason }) if reason == "MAX_TOKENS"
+        ));
+        assert!(error.to_string().contains("auto_continue"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_extract_refreshes_token_once_on_401() {
+        let transport = MockTransport::new();
+        transport.push_error(VertexError::Unauthorized("token expired".to_string()));
+        transport.push_response(serde_json::json!({
+            "candidates": [{
+                "content": { "parts": [{ "text": "{\"a\": 1}" }] },
+                "finishReason": "STOP"
+            }]
+        }));
+
+        let request = VertexAIRequest::new_pdf_extraction("AAAA", "extract", None);
+        let (value, _) = extract_data_with_transport(&transport, &request, &test_endpoint())
+            .await
+            .unwrap();
+        assert_eq!(value["a"], 1);
+        assert_eq!(transport.refresh_count(), 1);
+        assert_eq!(transport.requests().len(), 2);
+
+        // A 403 is a permission problem, so the token is not refreshed
+        let transport = MockTransport::new();
+        transport.push_error(VertexError::Api {
+            status: 403,
+            status_name: Some("PERMISSION_DENIED".to_string()),
+            message: "denied".to_string(),
+            request_id: None,
+        });
+        assert!(
+            extract_data_with_transport(&transport, &request, &test_endpoint())
+                .await
+                .is_err()
+        );
+        assert_eq!(transport.refresh_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cached_extraction_skips_the_api() {
+        let dir = std::env::temp_dir().join("hvertex-test-extraction-cache");
+        let _ = fs::remove_dir_all(&dir);
+        let options = ExtractionOptions {
+            project_id: Some("my-project".to_string()),
+            cache_dir: Some(dir.clone()),
+            ..ExtractionOptions::default()
+        };
+
+        let endpoint = ModelEndpoint::resolve(options.project_id.clone(), None, None).unwrap();
+        let key_with = |mime_type, prompt, part_order, candidate_count| {
+            cache_key(
+                b"%PDF-1.7",
+                mime_type,
+                prompt,
+                None,
+                &endpoint.model_id,
+                part_order,
+                candidate_count,
+            )
+        };
+        let key = key_with(
+            "application/pdf",
+            DEFAULT_EXTRACTION_PROMPT,
+            PartOrder::DataFirst,
+            None,
+        );
+        for other in [
+            key_with(
+                "application/pdf",
+                "another prompt",
+                PartOrder::DataFirst,
+                None,
+            ),
+            key_with(
+                "image/png",
+                DEFAULT_EXTRACTION_PROMPT,
+                PartOrder::DataFirst,
+                None,
+            ),
+            key_with(
+                "application/pdf",
+                DEFAULT_EXTRACTION_PROMPT,
+                PartOrder::TextFirst,
+                None,
+            ),
+            key_with(
+                "application/pdf",
+                DEFAULT_EXTRACTION_PROMPT,
+                PartOrder::DataFirst,
+                Some(2),
+            ),
+        ] {
+            assert_ne!(key, other);
+        }
+        write_cached(
+            &dir.join(format!("{}.json", key)),
+            &serde_json::json!({ "cached": true }),
+        )
+        .unwrap();
+
+        // A cache hit returns without contacting the (unreachable) API
+        let (value, usage) = extract_inline_with_usage(b"%PDF-1.7", "application/pdf", options)
+            .await
+            .unwrap();
+        assert_eq!(value["cached"], true);
+        assert_eq!(usage.total_token_count, 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_streaming_json_collector() {
+        let mut collector = StreamingJsonCollector::new();
+        for delta in ["Here you go:\n``", "`json\n{\"a\": [1, ", "2]}\n```"] {
+            collector.push(delta);
+        }
+        assert!(collector.text().starts_with("Here you go"));
+        assert_eq!(collector.finish().unwrap()["a"][1], 2);
+
+        let mut collector = StreamingJsonCollector::new();
+        collector.push("{\"a\": ");
+        assert!(collector.finish().is_err());
+    }
+
+    #[test]
+    fn test_deserialize_extracted() {
+        #[derive(Debug, serde::Deserialize)]
+        struct Contract {
+            contract_number: String,
+        }
+
+        let raw =
+            serde_json::json!({ "raw_text": "```json\n{\"contract_number\": \"C-42\"}\n```" });
+        let contract: Contract = deserialize_extracted(raw).unwrap();
+        assert_eq!(contract.contract_number, "C-42");
+
+        let error = deserialize_extracted::<Contract>(serde_json::json!({ "number": 42 }))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("contract_number"), "{}", error);
+        assert!(error.contains("\"number\": 42"), "{}", error);
+    }
+
+    #[test]
+    fn test_extract_json_without_code_blocks() {
+        assert_eq!(
+            extract_json_from_raw_text("{\"a\": 1}").unwrap(),
+            serde_json::json!({ "a": 1 })
+        );
+        assert!(extract_json_from_raw_text("no json here").is_err());
+    }
+
+    #[test]
+    fn test_extract_json_from_one_block() {
+        let text = "Here you go:\n```json\n{\"a\": 1}\n```\nLet me know!";
+        assert_eq!(
+            extract_json_from_raw_text(text).unwrap(),
+            serde_json::json!({ "a": 1 })
+        );
+    }
+
+    #[test]
+    fn test_extract_json_skips_invalid_blocks() {
+        let text = "```\nSummary: one contract\n```\nand\n```json\n{\"contract\": \"C-42\"}\n```";
+        assert_eq!(
+            extract_json_from_raw_text(text).unwrap(),
+            serde_json::json!({ "contract": "C-42" })
+        );
+
+        let text = "```json\n{\"a\": 1}\n```\n```json\n{\"b\": 2, \"c\": 3}\n```";
+        assert_eq!(
+            extract_json_from_raw_text(text).unwrap(),
+            serde_json::json!({ "b": 2, "c": 3 })
+        );
+        assert_eq!(
+            extract_json_from_raw_text_with(text, BlockSelection::All, false).unwrap(),
+            serde_json::json!([{ "a": 1 }, { "b": 2, "c": 3 }])
+        );
+    }
+
+    #[test]
+    fn test_extract_json_repairs_trailing_commas_when_lenient() {
+        let lenient =
+            |text: &str| extract_json_from_raw_text_with(text, BlockSelection::Largest, true);
+
+        let text = "```json\n{\"a\": 1, \"b\": {\"c\": 2,},\n}\n```";
+        assert!(extract_json_from_raw_text(text).is_err());
+        assert_eq!(
+            lenient(text).unwrap(),
+            serde_json::json!({ "a": 1, "b": { "c": 2 } })
+        );
+
+        let text = "[1, 2, [3, ],\n]";
+        assert_eq!(lenient(text).unwrap(), serde_json::json!([1, 2, [3]]));
+
+        // Commas and colons inside strings are kept
+        let text = "{note: \"x, }\", \"list\": [\"a,]\",]}";
+        assert_eq!(
+            lenient(text).unwrap(),
+            serde_json::json!({ "note": "x, }", "list": ["a,]"] })
+        );
+
+        assert!(lenient("{\"a\": 1,, \"b\": }").is_err());
+        assert!(lenient("no json here").is_err());
+    }
+
+    #[test]
+    fn test_extract_json_fixtures() {
+        let wrapped = serde_json::json!({
+            "raw_text": serde_json::json!({
+                "raw_text": "Result:\n```json\n{\"policy\": \"P-7\"}\n```"
+            })
+            .to_string()
+        })
+        .to_string();
+        let fixtures: Vec<(&str, String, serde_json::Value)> = vec![
+            (
+                "clean object",
+                "{\"policy\": \"P-1\", \"premium\": 120.5}".to_string(),
+                serde_json::json!({ "policy": "P-1", "premium": 120.5 }),
+            ),
+            (
+                "fenced with prose",
+                "Sure! Here is the data:\n\n```json\n{\"policy\": \"P-2\"}\n```\n\nAnything else?"
+                    .to_string(),
+                serde_json::json!({ "policy": "P-2" }),
+            ),
+            (
+                "doubly wrapped raw_text",
+                wrapped,
+                serde_json::json!({ "policy": "P-7" }),
+            ),
+            (
+                "string containing fences",
+                "```json\n{\"note\": \"```json\\n{}\\n```\"}\n```".to_string(),
+                serde_json::json!({ "note": "```json\n{}\n```" }),
+            ),
+        ];
+        for (name, input, expected) in fixtures {
+            let value =
+                extract_json_from_raw_text(&input).unwrap_or_else(|e| panic!("{}: {:#}", name, e));
+            assert_eq!(value, expected, "{}", name);
+        }
+
+        let error = extract_json_from_raw_text("The document could not be read.").unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("no code blocks were found"),
+            "{:#}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_extract_json_with_nested_backticks() {
+        let text = "```json\n{\"snippet\": \"run ```cargo test``` first\"}\n```";
+        assert_eq!(
+            extract_json_from_raw_text(text).unwrap(),
+            serde_json::json!({ "snippet": "run ```cargo test``` first" })
+        );
+    }
+
+    #[test]
+    fn test_validate_extraction_reports_each_violation() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "required": ["document_type", "contract_number", "accuracy_score"],
+            "properties": {
+                "accuracy_score": { "type": "number", "minimum": 0, "maximum": 1 }
+            }
+        });
+
+        let valid = serde_json::json!({
+            "document_type": "insurance_contract",
+            "contract_number": "C-42",
+            "accuracy_score": 0.93
+        });
+        assert!(validate_extraction(&valid, &schema).is_ok());
+
+        let invalid =
+            serde_json::json!({ "document_type": "insurance_contract", "accuracy_score": 7 });
+        let violations = validate_extraction(&invalid, &schema).unwrap_err();
+        assert_eq!(violations.len(), 2);
+        assert!(violations.iter().any(|v| v.contains("contract_number")));
+        assert!(violations.iter().any(|v| v.starts_with("/accuracy_score")));
+    }
+
+    #[test]
+    fn test_detect_mime_type() {
+        assert_eq!(detect_mime_type(b"%PDF-1.4\n"), Some("application/pdf"));
+        assert_eq!(
+            detect_mime_type(b"\x89PNG\r\n\x1a\n\0\0"),
+            Some("image/png")
+        );
+        assert_eq!(
+            detect_mime_type(&[0xFF, 0xD8, 0xFF, 0xE0]),
+            Some("image/jpeg")
+        );
+        assert_eq!(detect_mime_type(b"GIF89a"), Some("image/gif"));
+        assert_eq!(detect_mime_type(b"PK\x03\x04"), None);
+        assert_eq!(detect_mime_type(b""), None);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_body_matches_in_memory_request() {
+        let document: Vec<u8> = (0..STREAM_CHUNK_BYTES * 2 + 5)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let request = |data: &str| {
+            VertexAIRequest::new_inline_extraction(data, "application/pdf", "Extract", None)
+                .unwrap()
+        };
+        let body = serde_json::to_string(&request(STREAMED_DATA_MARKER)).unwrap();
+        let (prefix, suffix) = body.split_once(STREAMED_DATA_MARKER).unwrap();
+
+        let encoded = Arc::new(AtomicUsize::new(0));
+        let chunks: Vec<Vec<u8>> = base64_body_stream(
+            std::io::Cursor::new(document.clone()),
+            prefix.to_string(),
+            suffix.to_string(),
+            encoded.clone(),
+        )
+        .try_collect()
+        .await
+        .unwrap();
+
+        // Prefix, three document chunks, and suffix
+        assert_eq!(chunks.len(), 5);
+        let expected = request(&general_purpose::STANDARD.encode(&document));
+        assert_eq!(
+            String::from_utf8(chunks.concat()).unwrap(),
+            serde_json::to_string(&expected).unwrap()
+        );
+        assert_eq!(
+            encoded.load(Ordering::SeqCst),
+            document.len().div_ceil(3) * 4
+        );
+    }
+
+    #[test]
+    fn test_language_is_appended_to_system_instruction() {
+        let options = ExtractionOptions::default().language("FR");
+        let system_instruction = options.system_instruction_with_language().unwrap().unwrap();
+        assert!(system_instruction.starts_with(DEFAULT_SYSTEM_INSTRUCTION));
+        assert!(system_instruction.ends_with("preserve original-language values."));
+        assert!(system_instruction.contains("in French"));
+
+        let options = ExtractionOptions::default().language("xx");
+        let error = options.system_instruction_with_language().unwrap_err();
+        assert!(error.to_string().contains("unsupported language 'xx'"));
+        assert_eq!(
+            ExtractionOptions::default()
+                .system_instruction_with_language()
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_filter_by_confidence() {
+        let data = serde_json::json!({
+            "parties": [
+                { "value": "Acme SA", "confidence": "92%" },
+                { "value": "Unreadable", "confidence": 30 },
+                "Jane Doe"
+            ],
+            "company": {
+                "address": { "value": "1 rue de la Paix", "accuracy_score": 0.5 },
+                "name": "Acme SA",
+                "vat_number": "FR123",
+                "confidence_scores": { "vat_number": 0.2, "name": 0.99 }
+            },
+            "accuracy_score": 0.93
+        });
+
+        let filtered = filter_by_confidence(&data, 0.8);
+        assert_eq!(
+            filtered,
+            serde_json::json!({
+                "parties": [{ "value": "Acme SA", "confidence": "92%" }, "Jane Doe"],
+                "company": {
+                    "name": "Acme SA",
+                    "confidence_scores": { "vat_number": 0.2, "name": 0.99 }
+                },
+                "accuracy_score": 0.93
+            })
+        );
+        assert_eq!(filter_by_confidence(&data, 0.0), data);
+    }
 }
@@ -1,5 +1,7 @@
 use anyhow::Result;
 use std::{
+    collections::HashMap,
+    future::Future,
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -7,9 +9,18 @@ use tokio::{
     sync::{Mutex, Semaphore},
     time::sleep,
 };
+use tracing::{debug, instrument};
+
+use crate::retry::{is_rate_limited, retry_async, RetryPolicy};
+
+/// First backoff delay after a rate-limit error without a `Retry-After` header
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest backoff delay between rate-limit retries
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
 
 /// Configuration for the request queue
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct QueueConfig {
     /// Maximum number of tokens in the bucket
     pub max_tokens: usize,
@@ -19,6 +30,8 @@ pub struct QueueConfig {
     pub refill_interval: Duration,
     /// Maximum number of concurrent requests
     pub max_concurrent_requests: usize,
+    /// Retries after a rate-limit error before the error is returned
+    pub max_retries: u32,
 }
 
 impl Default for QueueConfig {
@@ -28,6 +41,53 @@ impl Default for QueueConfig {
             refill_tokens: 100000,                    // Refill 100k tokens per interval
             refill_interval: Duration::from_secs(60), // Refill every minute
             max_concurrent_requests: 3,
+            max_retries: 5,
+        }
+    }
+}
+
+impl QueueConfig {
+    /// Returns rate-limit presets for a Gemini model
+    ///
+    /// | Model family | Bucket size | Refill per minute | Concurrency |
+    /// |--------------|-------------|-------------------|-------------|
+    /// | `*flash*`    | 4,000,000   | 1,000,000         | 10          |
+    /// | `*pro*`      | 1,000,000   | 250,000           | 2           |
+    /// | other        | 1,000,000   | 100,000           | 3           |
+    ///
+    /// Other models get [`QueueConfig::default`]. Every field is public, so any
+    /// preset value can be overridden afterwards.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use hvertex::queue::QueueConfig;
+    ///
+    /// let config = QueueConfig {
+    ///     max_concurrent_requests: 1,
+    ///     ..QueueConfig::for_model("gemini-1.5-pro")
+    /// };
+    /// assert_eq!(config.max_tokens, 1_000_000);
+    /// ```
+    pub fn for_model(model_id: &str) -> QueueConfig {
+        if model_id.contains("flash") {
+            Self {
+                max_tokens: 4_000_000,
+                refill_tokens: 1_000_000,
+                refill_interval: Duration::from_secs(60),
+                max_concurrent_requests: 10,
+                ..Self::default()
+            }
+        } else if model_id.contains("pro") {
+            Self {
+                max_tokens: 1_000_000,
+                refill_tokens: 250_000,
+                refill_interval: Duration::from_secs(60),
+                max_concurrent_requests: 2,
+                ..Self::default()
+            }
+        } else {
+            Self::default()
         }
     }
 }
@@ -60,21 +120,37 @@ impl TokenBucket {
 
     /// Refills the token bucket based on elapsed time
     fn refill(&mut self) {
-        let now = Instant::now();
+        self.refill_at(Instant::now());
+    }
+
+    /// Refills the token bucket for the whole intervals elapsed before `now`
+    ///
+    /// `last_refill` only advances by the intervals credited, so the leftover
+    /// partial interval counts towards the next refill instead of being lost.
+    fn refill_at(&mut self, now: Instant) {
         let elapsed = now.duration_since(self.last_refill);
-        let intervals = (elapsed.as_nanos() / self.refill_interval.as_nanos()) as usize;
+        let intervals = (elapsed.as_nanos() / self.refill_interval.as_nanos()) as u32;
 
         if intervals > 0 {
-            self.tokens = (self.tokens + self.refill_tokens * intervals).min(self.max_tokens);
-            self.last_refill = now;
+            let refill = self.refill_tokens.saturating_mul(intervals as usize);
+            self.tokens = self.tokens.saturating_add(refill).min(self.max_tokens);
+            self.last_refill += self.refill_interval * intervals;
         }
     }
 
     /// Attempts to consume a token
+    #[cfg(test)]
     fn try_consume(&mut self) -> bool {
+        self.try_consume_n(1)
+    }
+
+    /// Attempts to consume `n` tokens at once
+    ///
+    /// Consumes nothing and returns false if fewer than `n` tokens are available.
+    fn try_consume_n(&mut self, n: usize) -> bool {
         self.refill();
-        if self.tokens > 0 {
-            self.tokens -= 1;
+        if self.tokens >= n {
+            self.tokens -= n;
             true
         } else {
             false
@@ -82,70 +158,195 @@ impl TokenBucket {
     }
 }
 
+/// A token bucket and concurrency limit shared by the requests it governs
+#[derive(Debug)]
+struct Limiter {
+    token_bucket: Mutex<TokenBucket>,
+    semaphore: Semaphore,
+    max_concurrent_requests: usize,
+    max_retries: u32,
+}
+
+impl Limiter {
+    fn new(config: &QueueConfig) -> Self {
+        Self {
+            token_bucket: Mutex::new(TokenBucket::new(config)),
+            semaphore: Semaphore::new(config.max_concurrent_requests),
+            max_concurrent_requests: config.max_concurrent_requests,
+            max_retries: config.max_retries,
+        }
+    }
+}
+
 /// Request queue with rate limiting
+///
+/// Requests sent with [`RequestQueue::execute_for_model`] are limited per model,
+/// so traffic to one model never waits on another model's quota.
 pub struct RequestQueue {
-    token_bucket: Arc<Mutex<TokenBucket>>,
-    semaphore: Arc<Semaphore>,
+    limiter: Arc<Limiter>,
+    model_configs: HashMap<String, QueueConfig>,
+    model_limiters: std::sync::Mutex<HashMap<String, Arc<Limiter>>>,
 }
 
 impl RequestQueue {
     /// Creates a new request queue with the specified configuration
     pub fn new(config: QueueConfig) -> Self {
         Self {
-            token_bucket: Arc::new(Mutex::new(TokenBucket::new(&config))),
-            semaphore: Arc::new(Semaphore::new(config.max_concurrent_requests)),
+            limiter: Arc::new(Limiter::new(&config)),
+            model_configs: HashMap::new(),
+            model_limiters: std::sync::Mutex::new(HashMap::new()),
         }
     }
 
+    /// Overrides the limits used for a model instead of [`QueueConfig::for_model`]
+    pub fn with_model_config(mut self, model_id: impl Into<String>, config: QueueConfig) -> Self {
+        self.model_configs.insert(model_id.into(), config);
+        self
+    }
+
+    /// Returns the limiter for a model, creating it on first use
+    fn model_limiter(&self, model_id: &str) -> Arc<Limiter> {
+        let mut limiters = self
+            .model_limiters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        limiters
+            .entry(model_id.to_string())
+            .or_insert_with(|| {
+                let config = self
+                    .model_configs
+                    .get(model_id)
+                    .cloned()
+                    .unwrap_or_else(|| QueueConfig::for_model(model_id));
+                Arc::new(Limiter::new(&config))
+            })
+            .clone()
+    }
+
     /// Executes a request with rate limiting and concurrency control
+    ///
+    /// Consumes a single token; use [`RequestQueue::execute_with_cost`] to account
+    /// for the request's actual token usage.
     pub async fn execute<F, T>(&self, request: F) -> Result<T>
     where
         F: FnOnce() -> Result<T> + Send + Clone + 'static,
         T: Send + 'static,
     {
-        // Acquire a permit from the semaphore
-        let _permit = self.semaphore.acquire().await?;
+        self.execute_with_cost(1, request).await
+    }
 
-        loop {
-            // Try to acquire a token
-            let can_proceed = {
-                let mut bucket = self.token_bucket.lock().await;
-                bucket.try_consume()
-            };
+    /// Executes a request that is estimated to consume `cost` tokens
+    ///
+    /// Waits until the bucket holds at least `cost` tokens before running the
+    /// request. Costs larger than the bucket capacity are clamped to it, so such
+    /// requests still run once the bucket is full.
+    pub async fn execute_with_cost<F, T>(&self, cost: usize, request: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T> + Send + Clone + 'static,
+        T: Send + 'static,
+    {
+        self.execute_async_with_cost(cost, || {
+            let request = request.clone();
+            async move { request() }
+        })
+        .await
+    }
 
-            if can_proceed {
-                // Clone the request for this attempt
-                let request = request.clone();
-
-                // Execute the request
-                match request() {
-                    Ok(result) => return Ok(result),
-                    Err(e) => {
-                        // If it's a rate limit error (429), wait and retry
-                        if e.to_string().contains("429") {
-                            sleep(Duration::from_secs(1)).await;
-                            continue;
-                        }
-                        return Err(e);
-                    }
-                }
-            }
+    /// Executes an async request that is estimated to consume `cost` tokens
+    ///
+    /// Behaves like [`RequestQueue::execute_with_cost`], but `request` returns a
+    /// future, so async API calls can be queued without blocking the runtime.
+    /// `request` is called again for each retry after a rate-limit error, up to
+    /// `QueueConfig::max_retries` times.
+    #[instrument(skip(self, request))]
+    pub async fn execute_async_with_cost<F, Fut, T>(&self, cost: usize, request: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        run_limited(&self.limiter, cost, request).await
+    }
 
-            // If no token is available, wait before retrying
-            sleep(Duration::from_millis(100)).await;
-        }
+    /// Executes an async request against the limits of a specific model
+    ///
+    /// Each model gets its own token bucket and concurrency limit, configured by
+    /// [`RequestQueue::with_model_config`] or else [`QueueConfig::for_model`].
+    #[instrument(skip(self, request))]
+    pub async fn execute_for_model<F, Fut, T>(
+        &self,
+        model_id: &str,
+        cost: usize,
+        request: F,
+    ) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let limiter = self.model_limiter(model_id);
+        run_limited(&limiter, cost, request).await
+    }
+
+    /// Returns the maximum number of requests that may run at once
+    pub fn max_concurrent_requests(&self) -> usize {
+        self.limiter.max_concurrent_requests
+    }
+
+    /// Returns how many more requests could start right now
+    ///
+    /// Zero means the queue is saturated and new requests wait for a slot.
+    pub fn remaining_permits(&self) -> usize {
+        self.limiter.semaphore.available_permits()
     }
 
     /// Returns the current number of available tokens
     pub async fn available_tokens(&self) -> usize {
-        let bucket = self.token_bucket.lock().await;
+        let bucket = self.limiter.token_bucket.lock().await;
         bucket.tokens
     }
 }
 
+/// Runs a request once the limiter has a free slot and `cost` tokens
+///
+/// Rate-limited requests are retried with [`retry_async`], and every attempt
+/// waits for its own tokens.
+async fn run_limited<F, Fut, T>(limiter: &Limiter, cost: usize, request: F) -> Result<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    // Acquire a permit from the semaphore
+    let _permit = limiter.semaphore.acquire().await?;
+
+    let policy = RetryPolicy {
+        max_retries: limiter.max_retries,
+        base_delay: BASE_RETRY_DELAY,
+        max_delay: MAX_RETRY_DELAY,
+    };
+    let request = &request;
+    retry_async(policy, is_rate_limited, move || async move {
+        // Wait until enough tokens are available for the request
+        loop {
+            let can_proceed = {
+                let mut bucket = limiter.token_bucket.lock().await;
+                let cost = cost.min(bucket.max_tokens);
+                bucket.try_consume_n(cost)
+            };
+            if can_proceed {
+                break;
+            }
+            debug!("Waiting for rate limit tokens");
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        request().await
+    })
+    .await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::vertex_ai::VertexError;
     use std::time::Duration;
 
     #[tokio::test]
@@ -155,6 +356,7 @@ mod tests {
             refill_tokens: 2,
             refill_interval: Duration::from_millis(100),
             max_concurrent_requests: 3,
+            ..QueueConfig::default()
         };
 
         let mut bucket = TokenBucket::new(&config);
@@ -171,4 +373,122 @@ mod tests {
         bucket.refill();
         assert_eq!(bucket.tokens, 4);
     }
+
+    #[test]
+    fn test_refill_carries_partial_intervals() {
+        let config = QueueConfig {
+            max_tokens: 1000,
+            refill_tokens: 5,
+            refill_interval: Duration::from_millis(100),
+            max_concurrent_requests: 3,
+            ..QueueConfig::default()
+        };
+
+        let mut bucket = TokenBucket::new(&config);
+        bucket.tokens = 0;
+        let start = bucket.last_refill;
+
+        // Refill every 90ms for 100 steps (9 seconds, so 90 full intervals)
+        let mut credited = 0;
+        for step in 1..=100 {
+            bucket.refill_at(start + Duration::from_millis(90 * step));
+            credited += bucket.tokens;
+            bucket.tokens = 0;
+        }
+
+        assert_eq!(credited, 90 * 5);
+    }
+
+    #[tokio::test]
+    async fn test_models_use_separate_buckets() {
+        let config = QueueConfig {
+            max_tokens: 10,
+            refill_tokens: 1,
+            refill_interval: Duration::from_secs(60),
+            max_concurrent_requests: 1,
+            ..QueueConfig::default()
+        };
+        let queue = RequestQueue::new(QueueConfig::default())
+            .with_model_config("model-a", config.clone())
+            .with_model_config("model-b", config);
+
+        queue
+            .execute_for_model("model-a", 10, || async { Ok(()) })
+            .await
+            .unwrap();
+
+        // model-a's bucket is now empty, but model-b must not wait on it
+        let result = tokio::time::timeout(
+            Duration::from_secs(1),
+            queue.execute_for_model("model-b", 10, || async { Ok("b") }),
+        )
+        .await
+        .expect("model-b waited on model-a's bucket");
+        assert_eq!(result.unwrap(), "b");
+    }
+
+    #[tokio::test]
+    async fn test_remaining_permits() {
+        let queue = RequestQueue::new(QueueConfig {
+            max_concurrent_requests: 2,
+            ..QueueConfig::default()
+        });
+        assert_eq!(queue.remaining_permits(), 2);
+
+        let seen = queue
+            .execute_async_with_cost(1, || async { Ok(queue.remaining_permits()) })
+            .await
+            .unwrap();
+        assert_eq!(seen, 1);
+        assert_eq!(queue.remaining_permits(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_rate_limit_gives_up() {
+        let queue = RequestQueue::new(QueueConfig {
+            max_retries: 3,
+            ..QueueConfig::default()
+        });
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+
+        let result: Result<()> = tokio::time::timeout(
+            Duration::from_secs(5),
+            queue.execute_async_with_cost(1, || {
+                attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                async {
+                    Err(VertexError::RateLimited {
+                        retry_after: Some(Duration::from_millis(1)),
+                    }
+                    .into())
+                }
+            }),
+        )
+        .await
+        .expect("the queue kept retrying a persistent 429");
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(VertexError::RateLimited { .. })
+        ));
+        assert_eq!(attempts.into_inner(), 4);
+    }
+
+    #[test]
+    fn test_try_consume_n_is_all_or_nothing() {
+        let config = QueueConfig {
+            max_tokens: 10,
+            refill_tokens: 2,
+            refill_interval: Duration::from_secs(60),
+            max_concurrent_requests: 3,
+            ..QueueConfig::default()
+        };
+
+        let mut bucket = TokenBucket::new(&config);
+        assert!(bucket.try_consume_n(7));
+        assert_eq!(bucket.tokens, 3);
+
+        // Not enough tokens left, so nothing is consumed
+        assert!(!bucket.try_consume_n(4));
+        assert_eq!(bucket.tokens, 3);
+    }
 }
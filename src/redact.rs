@@ -0,0 +1,165 @@
+//! Redaction module for the Vertex AI Setup Tool
+//!
+//! This module masks personal data in request and response bodies before they
+//! are written to debug logs, so verbose logging can be enabled in production
+//! without leaking document contents.
+
+use regex::Regex;
+use serde_json::Value;
+use std::sync::OnceLock;
+
+/// Characters of each text part kept when a request body is redacted
+pub const REDACTED_TEXT_LIMIT: usize = 80;
+
+/// Replacement for email addresses and phone numbers
+const PII_MASK: &str = "[REDACTED]";
+
+/// Returns the patterns matching email addresses and phone numbers
+fn pii_patterns() -> &'static [Regex] {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            // Email addresses
+            r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}",
+            // International numbers, e.g. "+33 6 12 34 56 78"
+            r"\+\d{1,3}(?:[\s.-]?\(?\d{1,4}\)?){3,6}",
+            // National numbers, e.g. "(555) 123-4567" or "020 7946 0958"
+            r"(?:\(\d{2,4}\)\s?|\b\d{2,4}[\s.-])\d{3,4}[\s.-]?\d{3,4}\b",
+        ]
+        .iter()
+        .map(|pattern| Regex::new(pattern).expect("PII patterns are valid"))
+        .collect()
+    })
+}
+
+/// Masks email addresses and phone numbers in `text`
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::redact::mask_pii;
+///
+/// assert_eq!(
+///     mask_pii("Contact jane@example.com or (555) 123-4567"),
+///     "Contact [REDACTED] or [REDACTED]"
+/// );
+/// ```
+pub fn mask_pii(text: &str) -> String {
+    pii_patterns()
+        .iter()
+        .fold(text.to_string(), |text, pattern| {
+            pattern.replace_all(&text, PII_MASK).into_owned()
+        })
+}
+
+/// Returns a copy of a request body that is safe to log
+///
+/// Inline data is replaced by its size, and text parts are truncated to
+/// `REDACTED_TEXT_LIMIT` characters with personal data masked.
+pub fn redact_request(body: &Value) -> Value {
+    redact(body, true)
+}
+
+/// Returns a copy of a response body with personal data masked in every string
+pub fn redact_response(body: &Value) -> Value {
+    redact(body, false)
+}
+
+/// Redacts `value` recursively, truncating `text` fields if `truncate` is set
+fn redact(value: &Value, truncate: bool) -> Value {
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| {
+                let value = match (key.as_str(), value) {
+                    // Requests serialize as `inline_data`; the API's JSON uses `inlineData`
+                    ("inline_data" | "inlineData", Value::Object(inline)) => {
+                        let mut inline = inline.clone();
+                        if let Some(Value::String(data)) = inline.get("data") {
+                            let summary = format!("<{} bytes of base64>", data.len());
+                            inline.insert("data".to_string(), Value::String(summary));
+                        }
+                        Value::Object(inline)
+                    }
+                    ("text", Value::String(text)) if truncate => {
+                        Value::String(mask_pii(&truncate_text(text)))
+                    }
+                    _ => redact(value, truncate),
+                };
+                (key.clone(), value)
+            })
+            .collect(),
+        Value::Array(items) => items.iter().map(|item| redact(item, truncate)).collect(),
+        Value::String(text) => Value::String(mask_pii(text)),
+        other => other.clone(),
+    }
+}
+
+/// Shortens `text` to `REDACTED_TEXT_LIMIT` characters, noting how much was cut
+fn truncate_text(text: &str) -> String {
+    let total = text.chars().count();
+    if total <= REDACTED_TEXT_LIMIT {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(REDACTED_TEXT_LIMIT).collect();
+    format!(
+        "{}... ({} more characters)",
+        kept,
+        total - REDACTED_TEXT_LIMIT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redact_request_and_response() {
+        let long_text = "x".repeat(REDACTED_TEXT_LIMIT + 20);
+        let request = json!({
+            "contents": [{
+                "role": "user",
+                "parts": [
+                    { "inlineData": { "mimeType": "application/pdf", "data": "JVBERi0xLjQK" } },
+                    { "text": long_text }
+                ]
+            }]
+        });
+        let redacted = redact_request(&request);
+        let parts = &redacted["contents"][0]["parts"];
+        assert_eq!(parts[0]["inlineData"]["data"], "<12 bytes of base64>");
+        assert_eq!(parts[0]["inlineData"]["mimeType"], "application/pdf");
+        assert!(parts[1]["text"]
+            .as_str()
+            .unwrap()
+            .ends_with("... (20 more characters)"));
+
+        let response = json!({
+            "candidates": [{ "content": { "parts": [{
+                "text": "{\"email\": \"jane.doe@example.com\", \"phone\": \"+33 6 12 34 56 78\", \"date\": \"2024-03-21\"}"
+            }] } }]
+        });
+        let text = redact_response(&response)["candidates"][0]["content"]["parts"][0]["text"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(!text.contains("jane.doe"));
+        assert!(!text.contains("56 78"));
+        assert!(text.contains("2024-03-21"));
+    }
+
+    #[test]
+    fn test_redact_serialized_pdf_request() {
+        let data = "JVBERi0xLjQK".repeat(100);
+        let request = crate::vertex_ai::VertexAIRequest::new_pdf_extraction(&data, "extract", None);
+        let body = serde_json::to_value(&request).unwrap();
+
+        let redacted = redact_request(&body);
+        assert!(!redacted.to_string().contains("JVBERi0xLjQK"));
+        assert_eq!(
+            redacted["contents"][0]["parts"][0]["inline_data"]["data"],
+            format!("<{} bytes of base64>", data.len())
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Retry module for the Vertex AI Setup Tool
+//!
+//! This module retries fallible async operations with jittered exponential
+//! backoff, using the same semantics as the crate's request queue, so flows
+//! built around [`crate::VertexClient`] can recover from transient failures.
+
+use anyhow::Result;
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::vertex_ai::VertexError;
+
+pub use crate::vertex_ai::RetryPolicy;
+
+/// Returns true for errors that usually succeed when retried
+///
+/// Covers rate limiting (HTTP 429), timeouts, and server errors (HTTP 500,
+/// 502, 503, and 504).
+pub fn is_transient(error: &VertexError) -> bool {
+    match error {
+        VertexError::RateLimited { .. } | VertexError::Timeout { .. } => true,
+        VertexError::Api { status, .. } => matches!(status, 500 | 502 | 503 | 504),
+        _ => false,
+    }
+}
+
+/// Returns true only for rate-limit errors (HTTP 429)
+pub fn is_rate_limited(error: &VertexError) -> bool {
+    matches!(error, VertexError::RateLimited { .. })
+}
+
+/// Returns the delay before retry `attempt` (starting at 0) after `error`
+///
+/// Honors the server's `Retry-After` for rate-limit errors, capped at
+/// `policy.max_delay`; otherwise uses the policy's jittered backoff.
+fn retry_delay(policy: &RetryPolicy, attempt: u32, error: &VertexError) -> Duration {
+    match error {
+        VertexError::RateLimited {
+            retry_after: Some(retry_after),
+        } => (*retry_after).min(policy.max_delay),
+        _ => policy.backoff_delay(attempt),
+    }
+}
+
+/// Runs an async operation, retrying it while it fails with a retryable error
+///
+/// `op` is called again for every retry, up to `policy.max_retries` times.
+/// Errors that are not a `VertexError`, or for which `is_retryable` returns
+/// false, are returned immediately.
+///
+/// # Arguments
+///
+/// * `policy` - How many times to retry and how long to wait between attempts
+/// * `is_retryable` - Decides which errors are worth retrying (e.g., [`is_transient`])
+/// * `op` - Starts one attempt of the operation
+///
+/// # Returns
+///
+/// * `Result<T, anyhow::Error>` - The first successful result, or the last error
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::retry::{is_transient, retry_async, RetryPolicy};
+/// use hvertex::{VertexAIRequest, VertexClient};
+///
+/// # async fn run(client: VertexClient, request: VertexAIRequest) -> anyhow::Result<()> {
+/// let response = retry_async(RetryPolicy::default(), is_transient, || {
+///     client.generate_content(&request)
+/// })
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn retry_async<F, Fut, T>(
+    policy: RetryPolicy,
+    is_retryable: fn(&VertexError) -> bool,
+    mut op: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        let error = match op().await {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+
+        let delay = match error.downcast_ref::<VertexError>() {
+            Some(vertex_error) if is_retryable(vertex_error) => {
+                if attempt >= policy.max_retries {
+                    warn!(attempt, error = %vertex_error, "Giving up after retries");
+                    return Err(error);
+                }
+                retry_delay(&policy, attempt, vertex_error)
+            }
+            _ => return Err(error),
+        };
+
+        attempt += 1;
+        warn!(?delay, attempt, error = %error, "Retrying after a transient error");
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn fast_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_async_succeeds_after_failures() {
+        let attempts = AtomicU32::new(0);
+        let result = retry_async(fast_policy(3), is_transient, || async {
+            if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(VertexError::Timeout {
+                    timeout: Duration::from_secs(1),
+                }
+                .into())
+            } else {
+                Ok("done")
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), "done");
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_async_stops_on_permanent_errors() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = retry_async(fast_policy(3), is_transient, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(VertexError::Unauthorized("expired".to_string()).into())
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // A persistent transient error is retried max_retries times, then returned
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = retry_async(fast_policy(2), is_transient, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(VertexError::RateLimited { retry_after: None }.into())
+        })
+        .await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(VertexError::RateLimited { .. })
+        ));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_retry_delay_honors_retry_after() {
+        let policy = RetryPolicy::default();
+        let rate_limited = |seconds| VertexError::RateLimited {
+            retry_after: Some(Duration::from_secs(seconds)),
+        };
+        assert_eq!(
+            retry_delay(&policy, 3, &rate_limited(7)),
+            Duration::from_secs(7)
+        );
+        assert_eq!(
+            retry_delay(&policy, 0, &rate_limited(600)),
+            policy.max_delay
+        );
+
+        for attempt in 0..10 {
+            let backoff = (policy.base_delay * 2u32.pow(attempt)).min(policy.max_delay);
+            let delay = retry_delay(&policy, attempt, &VertexError::Cancelled);
+            assert!(delay >= backoff / 2 && delay <= backoff, "{:?}", delay);
+        }
+    }
+}
@@ -1,9 +1,324 @@
 use anyhow::{Context, Result};
 use serde_json::Value;
-use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, instrument};
+
+use crate::config::ConfigError;
+use crate::gcloud::run_gcloud;
+use crate::vertex_ai::VertexError;
+
+#[cfg(feature = "blocking")]
+use crate::auth::{get_access_token, token_project};
+#[cfg(feature = "blocking")]
+use crate::config::Config;
+#[cfg(feature = "blocking")]
+use crate::vertex_ai::{
+    api_base_url, api_error, auth_headers, endpoint_override_from_env, new_request_id,
+    parse_endpoint_override, shared_blocking_client, ModelEndpoint, GLOBAL_LOCATION,
+    REQUEST_ID_HEADER,
+};
+#[cfg(feature = "blocking")]
+use serde::Deserialize;
+#[cfg(feature = "blocking")]
+use tracing::warn;
+
+#[cfg(feature = "async")]
+use crate::models::list_publisher_models;
+#[cfg(feature = "async")]
+use crate::vertex_ai::{count_tokens, VertexAIRequest};
+#[cfg(feature = "async")]
+use std::collections::HashMap;
+#[cfg(feature = "async")]
+use std::io::IsTerminal;
+#[cfg(feature = "async")]
+use std::sync::{Mutex, OnceLock};
+
+/// Locations where Vertex AI generative models are available
+///
+/// "global" selects the non-regional `aiplatform.googleapis.com` endpoint.
+pub const SUPPORTED_REGIONS: &[&str] = &[
+    "global",
+    "us-central1",
+    "us-east1",
+    "us-east4",
+    "us-east5",
+    "us-south1",
+    "us-west1",
+    "us-west4",
+    "northamerica-northeast1",
+    "southamerica-east1",
+    "europe-central2",
+    "europe-north1",
+    "europe-southwest1",
+    "europe-west1",
+    "europe-west2",
+    "europe-west3",
+    "europe-west4",
+    "europe-west6",
+    "europe-west8",
+    "europe-west9",
+    "asia-east1",
+    "asia-east2",
+    "asia-northeast1",
+    "asia-northeast3",
+    "asia-south1",
+    "asia-southeast1",
+    "australia-southeast1",
+    "me-central1",
+    "me-central2",
+    "me-west1",
+];
+
+/// Checks that a region is a known Vertex AI location
+///
+/// # Arguments
+///
+/// * `region` - The region to validate (e.g., "us-central1")
+///
+/// # Returns
+///
+/// * `Result<(), anyhow::Error>` - `ConfigError::InvalidConfig` listing the valid regions on mismatch
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::setup::validate_region;
+///
+/// assert!(validate_region("europe-west4").is_ok());
+/// assert!(validate_region("us-central-1").is_err());
+/// ```
+pub fn validate_region(region: &str) -> Result<()> {
+    if SUPPORTED_REGIONS.contains(&region) {
+        return Ok(());
+    }
+
+    let suggestion = suggest_region(region, SUPPORTED_REGIONS)
+        .map(|nearest| format!(" Did you mean '{}'?", nearest))
+        .unwrap_or_default();
+    Err(ConfigError::InvalidConfig(format!(
+        "Unknown Vertex AI region '{}'.{} Valid regions are: {}",
+        region,
+        suggestion,
+        SUPPORTED_REGIONS.join(", ")
+    ))
+    .into())
+}
+
+/// Returns the candidate region closest in spelling to `region`
+///
+/// Catches typos such as "us-central-1" or "europe-west5"; returns None when no
+/// candidate is within a few edits.
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::setup::{suggest_region, SUPPORTED_REGIONS};
+///
+/// assert_eq!(suggest_region("us-central-1", SUPPORTED_REGIONS), Some("us-central1"));
+/// assert_eq!(suggest_region("mars", SUPPORTED_REGIONS), None);
+/// ```
+pub fn suggest_region<'a>(region: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    const MAX_DISTANCE: usize = 3;
+
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(region, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= MAX_DISTANCE)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// One page of results from the locations endpoint
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg(feature = "blocking")]
+struct LocationsPage {
+    #[serde(default)]
+    locations: Vec<Location>,
+    #[serde(default)]
+    next_page_token: Option<String>,
+}
+
+/// A location returned by the locations endpoint
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg(feature = "blocking")]
+struct Location {
+    location_id: String,
+}
+
+/// Lists the regions where Vertex AI is available to a project
+///
+/// Queries the `locations` REST endpoint, following `nextPageToken` until every
+/// page has been fetched. Unlike [`SUPPORTED_REGIONS`], the result reflects
+/// what Google currently serves to this project.
+///
+/// # Arguments
+///
+/// * `project_id` - The Google Cloud project ID
+/// * `endpoint_override` - A private endpoint to query instead of the public
+///   API host (defaults to `VERTEX_AI_ENDPOINT_OVERRIDE`)
+///
+/// # Returns
+///
+/// * `Result<Vec<String>, anyhow::Error>` - Region ids (e.g., "us-central1") or error
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::setup::list_available_regions;
+///
+/// for region in list_available_regions("my-project-id", None)? {
+///     println!("{}", region);
+/// }
+/// Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg(feature = "blocking")]
+pub fn list_available_regions(
+    project_id: &str,
+    endpoint_override: Option<&str>,
+) -> Result<Vec<String>> {
+    let access_token = get_access_token()?;
+    let headers = auth_headers(&access_token)?;
+    let client = shared_blocking_client()?;
+    let endpoint_override = match endpoint_override {
+        Some(url) => Some(parse_endpoint_override(url)?),
+        None => endpoint_override_from_env()?,
+    };
+    let api_url = format!(
+        "{}/v1/projects/{}/locations",
+        api_base_url(GLOBAL_LOCATION, endpoint_override.as_deref()),
+        project_id
+    );
+
+    let mut regions = Vec::new();
+    let mut page_token: Option<String> = None;
+    loop {
+        let mut request = client.get(&api_url).headers(headers.clone());
+        if let Some(token) = &page_token {
+            request = request.query(&[("pageToken", token)]);
+        }
+
+        let response = request.send().context("Failed to request locations")?;
+        let status = response.status();
+        if !status.is_success() {
+            let headers = response.headers().clone();
+            let error_text = response
+                .text()
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(api_error(status, &headers, error_text).into());
+        }
+
+        let page: LocationsPage = response
+            .json()
+            .map_err(|e| VertexError::Parse(e.to_string()))
+            .context("Failed to parse locations response")?;
+        regions.extend(
+            page.locations
+                .into_iter()
+                .map(|location| location.location_id),
+        );
+
+        match page.next_page_token.filter(|token| !token.is_empty()) {
+            Some(token) => page_token = Some(token),
+            None => break,
+        }
+    }
+
+    Ok(regions)
+}
+
+/// Options controlling how [`ensure_vertex_ai_service_with_options`] checks the project
+#[derive(Debug, Clone)]
+pub struct ServiceOptions {
+    /// Whether to verify that the project has an active billing account
+    ///
+    /// Disable this if the caller lacks billing-viewer permission.
+    pub check_billing: bool,
+    /// How long to wait for the service to become active after enabling it
+    pub activation_timeout: Duration,
+    /// How often to check whether the service has become active
+    pub poll_interval: Duration,
+    /// Whether to ask gcloud for the Vertex AI service alone instead of
+    /// listing every enabled service, falling back to the full list when the
+    /// installed gcloud cannot filter
+    pub targeted_check: bool,
+}
+
+impl Default for ServiceOptions {
+    fn default() -> Self {
+        Self {
+            check_billing: true,
+            activation_timeout: Duration::from_secs(60),
+            poll_interval: Duration::from_secs(5),
+            targeted_check: true,
+        }
+    }
+}
+
+/// Checks whether the project is linked to an active billing account
+///
+/// # Arguments
+///
+/// * `project_id` - The Google Cloud project ID
+///
+/// # Returns
+///
+/// * `Result<bool, anyhow::Error>` - Whether billing is enabled, or error
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::setup::ensure_billing_enabled;
+///
+/// if !ensure_billing_enabled("my-project-id")? {
+///     eprintln!("Link a billing account before using Vertex AI");
+/// }
+/// Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn ensure_billing_enabled(project_id: &str) -> Result<bool> {
+    let output = run_gcloud(&[
+        "billing",
+        "projects",
+        "describe",
+        project_id,
+        "--format=json",
+    ])?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("Failed to check billing status: {}", error));
+    }
+
+    let billing_info: Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| VertexError::Parse(e.to_string()))
+        .context("Failed to parse billing info output")?;
+
+    Ok(billing_info["billingEnabled"].as_bool().unwrap_or(false))
+}
 
 /// Ensures the Vertex AI service is enabled in the project
 ///
+/// Also verifies that billing is enabled; use
+/// [`ensure_vertex_ai_service_with_options`] to skip that check. After enabling
+/// the service, waits up to 60 seconds for it to become active.
+///
 /// # Arguments
 ///
 /// * `project_id` - The Google Cloud project ID
@@ -22,22 +337,126 @@ use std::process::Command;
 /// Ok::<(), Box<dyn std::error::Error>>(())
 /// ```
 pub fn ensure_vertex_ai_service(project_id: &str) -> Result<()> {
-    // Check if Vertex AI service is enabled
-    let output = Command::new("gcloud")
-        .args(["services", "list", "--project", project_id, "--format=json"])
-        .output()
-        .context("Failed to execute gcloud services list command")?;
+    ensure_vertex_ai_service_with_options(project_id, &ServiceOptions::default())
+}
+
+/// Ensures the Vertex AI service is enabled in the project, with custom options
+///
+/// # Arguments
+///
+/// * `project_id` - The Google Cloud project ID
+/// * `options` - Which additional checks to run and how long to wait for activation
+///
+/// # Returns
+///
+/// * `Result<(), anyhow::Error>` - Success, `VertexError::BillingDisabled`, or another error
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::setup::{ensure_vertex_ai_service_with_options, ServiceOptions};
+///
+/// let options = ServiceOptions {
+///     check_billing: false,
+///     ..ServiceOptions::default()
+/// };
+/// ensure_vertex_ai_service_with_options("my-project-id", &options)?;
+/// Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[instrument(skip(options), fields(check_billing = options.check_billing))]
+pub fn ensure_vertex_ai_service_with_options(
+    project_id: &str,
+    options: &ServiceOptions,
+) -> Result<()> {
+    // Enabling the API succeeds without billing, but every request would then fail
+    if options.check_billing && !ensure_billing_enabled(project_id)? {
+        return Err(VertexError::BillingDisabled(project_id.to_string()).into());
+    }
+
+    if is_vertex_ai_enabled(project_id, options.targeted_check)? {
+        debug!("Vertex AI service already enabled");
+        return Ok(());
+    }
+
+    // Enable Vertex AI service
+    info!("Enabling Vertex AI service");
+    let enable_output = run_gcloud(&[
+        "services",
+        "enable",
+        "aiplatform.googleapis.com",
+        "--project",
+        project_id,
+    ])?;
+
+    if !enable_output.status.success() {
+        let error = String::from_utf8_lossy(&enable_output.stderr);
+        return Err(VertexError::ServiceNotEnabled(error.to_string()).into());
+    }
+
+    // The enable call returns before the service propagates, so wait until it is listed
+    let deadline = Instant::now() + options.activation_timeout;
+    loop {
+        if is_vertex_ai_enabled(project_id, options.targeted_check)? {
+            info!("Vertex AI service is active");
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(VertexError::ServiceNotEnabled(format!(
+                "aiplatform.googleapis.com did not become active within {:?}",
+                options.activation_timeout
+            ))
+            .into());
+        }
+        debug!("Waiting for Vertex AI service to become active");
+        thread::sleep(options.poll_interval);
+    }
+}
+
+/// Checks whether `aiplatform.googleapis.com` is listed as enabled in the project
+///
+/// With `targeted`, gcloud is asked for that service alone, which avoids
+/// fetching and parsing the project's whole service catalog. Versions of
+/// gcloud that reject the filter fall back to the full list.
+fn is_vertex_ai_enabled(project_id: &str, targeted: bool) -> Result<bool> {
+    if targeted {
+        let output = run_gcloud(&[
+            "services",
+            "list",
+            "--enabled",
+            "--filter=config.name=aiplatform.googleapis.com",
+            "--project",
+            project_id,
+            "--format=json",
+        ])?;
+        if output.status.success() {
+            return lists_vertex_ai(&output.stdout);
+        }
+
+        let error = String::from_utf8_lossy(&output.stderr);
+        if !error.contains("unrecognized arguments") {
+            return Err(anyhow::anyhow!("Failed to list services: {}", error));
+        }
+        debug!("gcloud cannot filter services, listing all of them");
+    }
+
+    let output = run_gcloud(&["services", "list", "--project", project_id, "--format=json"])?;
 
     if !output.status.success() {
         let error = String::from_utf8_lossy(&output.stderr);
         return Err(anyhow::anyhow!("Failed to list services: {}", error));
     }
 
+    lists_vertex_ai(&output.stdout)
+}
+
+/// Checks whether `gcloud services list` output includes the Vertex AI service
+fn lists_vertex_ai(stdout: &[u8]) -> Result<bool> {
     // Parse the JSON output to check if Vertex AI is enabled
-    let services: Value =
-        serde_json::from_slice(&output.stdout).context("Failed to parse services list output")?;
+    let services: Value = serde_json::from_slice(stdout)
+        .map_err(|e| VertexError::Parse(e.to_string()))
+        .context("Failed to parse services list output")?;
 
-    let vertex_ai_enabled = services
+    Ok(services
         .as_array()
         .map(|arr| {
             arr.iter().any(|service| {
@@ -46,31 +465,390 @@ pub fn ensure_vertex_ai_service(project_id: &str) -> Result<()> {
                     .is_some_and(|name| name.contains("aiplatform.googleapis.com"))
             })
         })
-        .unwrap_or(false);
-
-    if !vertex_ai_enabled {
-        // Enable Vertex AI service
-        let enable_output = Command::new("gcloud")
-            .args([
-                "services",
-                "enable",
-                "aiplatform.googleapis.com",
-                "--project",
+        .unwrap_or(false))
+}
+
+/// A capability checked by [`preflight`]
+#[cfg(feature = "blocking")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreflightCheck {
+    /// The gcloud CLI is installed and on PATH
+    Gcloud,
+    /// gcloud can issue an access token
+    Authenticated,
+    /// The access token belongs to the configured project (opt-in, see
+    /// [`PreflightOptions::check_token_project`])
+    TokenProject,
+    /// The configured region is a known Vertex AI location
+    Region,
+    /// The project has an active billing account
+    Billing,
+    /// `aiplatform.googleapis.com` is enabled in the project
+    ServiceEnabled,
+    /// A sample request to the configured model succeeds
+    ApiCall,
+}
+
+#[cfg(feature = "blocking")]
+impl PreflightCheck {
+    /// Short description of the check, for rendering a checklist
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Gcloud => "gcloud CLI installed",
+            Self::Authenticated => "gcloud authenticated",
+            Self::TokenProject => "Token matches project",
+            Self::Region => "Region valid",
+            Self::Billing => "Billing linked",
+            Self::ServiceEnabled => "Vertex AI service enabled",
+            Self::ApiCall => "Test API call succeeds",
+        }
+    }
+}
+
+/// The outcome of a single preflight check
+#[cfg(feature = "blocking")]
+#[derive(Debug, Clone)]
+pub struct PreflightResult {
+    /// Which capability was checked
+    pub check: PreflightCheck,
+    /// Whether the check passed
+    pub passed: bool,
+    /// What was found, or why the check failed
+    pub message: String,
+}
+
+/// The outcome of every check run by [`preflight`], in the order they ran
+#[cfg(feature = "blocking")]
+#[derive(Debug, Clone, Default)]
+pub struct PreflightReport {
+    /// One result per check
+    pub results: Vec<PreflightResult>,
+}
+
+#[cfg(feature = "blocking")]
+impl PreflightReport {
+    /// Returns true if every check passed
+    pub fn all_passed(&self) -> bool {
+        self.results.iter().all(|result| result.passed)
+    }
+
+    /// Returns the checks that failed
+    pub fn failures(&self) -> impl Iterator<Item = &PreflightResult> {
+        self.results.iter().filter(|result| !result.passed)
+    }
+
+    /// Returns the result of a specific check, if it ran
+    pub fn get(&self, check: PreflightCheck) -> Option<&PreflightResult> {
+        self.results.iter().find(|result| result.check == check)
+    }
+
+    /// Records the outcome of a check
+    fn record(&mut self, check: PreflightCheck, outcome: Result<String>) {
+        let (passed, message) = match outcome {
+            Ok(message) => (true, message),
+            Err(e) => (false, format!("{:#}", e)),
+        };
+        self.results.push(PreflightResult {
+            check,
+            passed,
+            message,
+        });
+    }
+}
+
+/// Optional checks run by [`preflight_with`]
+#[cfg(feature = "blocking")]
+#[derive(Debug, Clone, Default)]
+pub struct PreflightOptions {
+    /// Checks that the access token belongs to the configured project, which
+    /// costs an extra request to Google's tokeninfo endpoint
+    pub check_token_project: bool,
+}
+
+/// Runs every setup check and reports which ones pass
+///
+/// Unlike [`ensure_vertex_ai_service`], this never changes the project: a
+/// disabled service is reported rather than enabled. Every check runs even if
+/// an earlier one fails, so the report shows everything that needs fixing.
+///
+/// # Arguments
+///
+/// * `config` - The project, region, and model to check
+///
+/// # Returns
+///
+/// * `PreflightReport` - A pass/fail result and message for each check
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::setup::preflight;
+/// use hvertex::Config;
+///
+/// let config = Config {
+///     project_id: "my-project-id".to_string(),
+///     ..Config::default()
+/// };
+/// for result in preflight(&config).results {
+///     let mark = if result.passed { "✅" } else { "❌" };
+///     println!("{} {}: {}", mark, result.check.description(), result.message);
+/// }
+/// ```
+#[cfg(feature = "blocking")]
+pub fn preflight(config: &Config) -> PreflightReport {
+    preflight_with(config, &PreflightOptions::default())
+}
+
+/// Runs every setup check, plus the optional checks enabled in `options`
+///
+/// With `check_token_project`, a token issued for another project than
+/// `config.project_id` fails the [`PreflightCheck::TokenProject`] check and is
+/// logged as a warning; it usually explains otherwise confusing permission errors.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::setup::{preflight_with, PreflightCheck, PreflightOptions};
+/// use hvertex::Config;
+///
+/// let options = PreflightOptions {
+///     check_token_project: true,
+/// };
+/// let report = preflight_with(&Config::default(), &options);
+/// if let Some(result) = report.get(PreflightCheck::TokenProject).filter(|r| !r.passed) {
+///     eprintln!("{}", result.message);
+/// }
+/// ```
+#[cfg(feature = "blocking")]
+pub fn preflight_with(config: &Config, options: &PreflightOptions) -> PreflightReport {
+    let project_id = config.project_id.as_str();
+    let mut report = PreflightReport::default();
+
+    report.record(
+        PreflightCheck::Gcloud,
+        run_gcloud(&["--version"]).and_then(|output| {
+            let version = String::from_utf8_lossy(&output.stdout);
+            match version.lines().next() {
+                Some(first_line) if output.status.success() => Ok(first_line.to_string()),
+                _ => Err(anyhow::anyhow!("`gcloud --version` failed")),
+            }
+        }),
+    );
+
+    let token = get_access_token();
+    report.record(
+        PreflightCheck::Authenticated,
+        match &token {
+            Ok(_) => Ok("Access token available".to_string()),
+            Err(e) => Err(anyhow::anyhow!("{:#}", e)),
+        },
+    );
+
+    if options.check_token_project {
+        report.record(
+            PreflightCheck::TokenProject,
+            token
+                .and_then(|token| token_project(&token))
+                .and_then(|token_project| check_token_project(token_project, project_id)),
+        );
+    }
+
+    report.record(
+        PreflightCheck::Region,
+        validate_region(&config.region).map(|_| config.region.clone()),
+    );
+
+    report.record(
+        PreflightCheck::Billing,
+        ensure_billing_enabled(project_id).and_then(|enabled| {
+            if enabled {
+                Ok(format!("Billing is enabled for {}", project_id))
+            } else {
+                Err(VertexError::BillingDisabled(project_id.to_string()).into())
+            }
+        }),
+    );
+
+    report.record(
+        PreflightCheck::ServiceEnabled,
+        is_vertex_ai_enabled(project_id, true).and_then(|enabled| {
+            if enabled {
+                Ok("aiplatform.googleapis.com is enabled".to_string())
+            } else {
+                Err(VertexError::ServiceNotEnabled(
+                    "aiplatform.googleapis.com is not enabled".to_string(),
+                )
+                .into())
+            }
+        }),
+    );
+
+    report.record(
+        PreflightCheck::ApiCall,
+        ModelEndpoint::resolve(
+            Some(project_id.to_string()),
+            Some(&config.region),
+            Some(&config.model),
+        )
+        .and_then(|endpoint| endpoint.with_endpoint_override(config.endpoint_override.as_deref()))
+        .and_then(|endpoint| send_test_request(&endpoint))
+        .map(|_| format!("{} responded", config.model)),
+    );
+
+    report
+}
+
+/// Compares the access token's project with the configured one
+#[cfg(feature = "blocking")]
+fn check_token_project(token_project: Option<String>, project_id: &str) -> Result<String> {
+    match token_project {
+        Some(token_project) if token_project != project_id => {
+            warn!(
+                token_project = %token_project,
+                project_id,
+                "Access token belongs to a different project"
+            );
+            Err(anyhow::anyhow!(
+                "The access token belongs to project {}, but the configured project is {}; \
+                 requests may fail with permission errors. Run \
+                 `gcloud auth application-default set-quota-project {}` or set \
+                 VERTEX_AI_PROJECT_ID to {}",
+                token_project,
+                project_id,
                 project_id,
-            ])
-            .output()
-            .context("Failed to enable Vertex AI service")?;
-
-        if !enable_output.status.success() {
-            let error = String::from_utf8_lossy(&enable_output.stderr);
-            return Err(anyhow::anyhow!(
-                "Failed to enable Vertex AI service: {}",
-                error
-            ));
+                token_project
+            ))
         }
+        Some(token_project) => Ok(format!("Access token belongs to {}", token_project)),
+        None => Ok("Could not determine the access token's project".to_string()),
     }
+}
 
-    Ok(())
+/// Probe results keyed by (project, region, model), kept for the life of the process
+#[cfg(feature = "async")]
+type ProbeCache = Mutex<HashMap<(String, String, String), bool>>;
+
+/// Returns the session-wide cache of model access probes
+#[cfg(feature = "async")]
+fn probe_cache() -> &'static ProbeCache {
+    static CACHE: OnceLock<ProbeCache> = OnceLock::new();
+    CACHE.get_or_init(ProbeCache::default)
+}
+
+/// Checks whether the project can call a model, caching the answer for the session
+///
+/// Sends a one-word `:countTokens` request, which is free but still enforces
+/// model allowlisting, so a model that would 403 on a real call is caught here.
+#[cfg(feature = "async")]
+async fn probe_model_access(project_id: &str, region: &str, model_id: &str) -> bool {
+    let key = (
+        project_id.to_string(),
+        region.to_string(),
+        model_id.to_string(),
+    );
+    if let Some(accessible) = probe_cache()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get(&key)
+    {
+        return *accessible;
+    }
+
+    let mut request = VertexAIRequest::new_chat(Vec::new());
+    request.push_user_text("ping");
+    let accessible = match count_tokens(
+        &request,
+        Some(project_id.to_string()),
+        Some(region),
+        Some(model_id),
+    )
+    .await
+    {
+        Ok(_) => true,
+        Err(e) => {
+            debug!(model = model_id, "Model is not accessible: {:#}", e);
+            false
+        }
+    };
+
+    probe_cache()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(key, accessible);
+    accessible
+}
+
+/// Lets the user pick a Gemini model the project can actually call
+///
+/// Lists the Google publisher models in the region and probes each Gemini
+/// model for access (some require allowlisting). When stdin is a terminal and
+/// more than one model is accessible, a numbered menu is shown; otherwise the
+/// first accessible model is returned. Probe results are cached for the session.
+///
+/// # Arguments
+///
+/// * `project_id` - The Google Cloud project ID
+/// * `region` - The region to list models from
+///
+/// # Returns
+///
+/// * `Result<String, anyhow::Error>` - The chosen model id (e.g., "gemini-2.0-flash"),
+///   or an error if no Gemini model is accessible
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use hvertex::setup::select_accessible_model;
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let model = select_accessible_model("my-project-id", "us-central1").await?;
+/// println!("Using {}", model);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub async fn select_accessible_model(project_id: &str, region: &str) -> Result<String> {
+    let candidates: Vec<String> = list_publisher_models(project_id, region)
+        .await?
+        .into_iter()
+        .map(|model| model_id_from_name(&model.name).to_string())
+        .filter(|model_id| model_id.starts_with("gemini"))
+        .collect();
+
+    let probes = candidates
+        .iter()
+        .map(|model_id| probe_model_access(project_id, region, model_id));
+    let accessible: Vec<String> = candidates
+        .iter()
+        .zip(futures::future::join_all(probes).await)
+        .filter(|(_, accessible)| *accessible)
+        .map(|(model_id, _)| model_id.clone())
+        .collect();
+
+    match accessible.as_slice() {
+        [] => Err(anyhow::anyhow!(
+            "No Gemini model in {} is accessible to project {}",
+            region,
+            project_id
+        )),
+        [only] => Ok(only.clone()),
+        _ if !std::io::stdin().is_terminal() => Ok(accessible[0].clone()),
+        _ => {
+            let choice = dialoguer::Select::new()
+                .with_prompt("Choose a model")
+                .items(&accessible)
+                .default(0)
+                .interact()
+                .context("Failed to read model selection")?;
+            Ok(accessible[choice].clone())
+        }
+    }
+}
+
+/// Strips the resource prefix from a publisher model name
+#[cfg(feature = "async")]
+fn model_id_from_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
 }
 
 /// Tests the Vertex AI API with a sample request
@@ -78,6 +856,7 @@ pub fn ensure_vertex_ai_service(project_id: &str) -> Result<()> {
 /// # Arguments
 ///
 /// * `project_id` - The Google Cloud project ID
+/// * `region` - The region to send the request to (defaults to us-central1)
 /// * `model` - The model to test with
 ///
 /// # Returns
@@ -91,31 +870,26 @@ pub fn ensure_vertex_ai_service(project_id: &str) -> Result<()> {
 ///
 /// let project_id = "my-project-id";
 /// let model = "gemini-pro";
-/// test_vertex_ai_api_call(project_id, model)?;
+/// test_vertex_ai_api_call(project_id, Some("europe-west4"), model)?;
 /// Ok::<(), Box<dyn std::error::Error>>(())
 /// ```
-pub fn test_vertex_ai_api_call(project_id: &str, model: &str) -> Result<()> {
-    // Get access token
-    let token_output = Command::new("gcloud")
-        .args(["auth", "print-access-token"])
-        .output()
-        .context("Failed to get access token")?;
-
-    if !token_output.status.success() {
-        let error = String::from_utf8_lossy(&token_output.stderr);
-        return Err(anyhow::anyhow!("Failed to get access token: {}", error));
-    }
+#[cfg(feature = "blocking")]
+pub fn test_vertex_ai_api_call(project_id: &str, region: Option<&str>, model: &str) -> Result<()> {
+    send_test_request(&ModelEndpoint::resolve(
+        Some(project_id.to_string()),
+        region,
+        Some(model),
+    )?)
+}
 
-    let access_token = String::from_utf8(token_output.stdout)
-        .context("Failed to parse access token")?
-        .trim()
-        .to_string();
+/// Sends the sample request of [`test_vertex_ai_api_call`] to `endpoint`
+#[cfg(feature = "blocking")]
+fn send_test_request(endpoint: &ModelEndpoint) -> Result<()> {
+    // Get access token
+    let access_token = get_access_token()?;
 
     // Construct the API URL
-    let api_url = format!(
-        "https://{}-aiplatform.googleapis.com/v1/projects/{}/locations/{}/publishers/google/models/{}:generateContent",
-        "us-central1", project_id, "us-central1", model
-    );
+    let api_url = endpoint.url("generateContent");
 
     // Create a test request
     let request_body = serde_json::json!({
@@ -132,21 +906,183 @@ pub fn test_vertex_ai_api_call(project_id: &str, model: &str) -> Result<()> {
     });
 
     // Make the API request using reqwest
-    let client = reqwest::blocking::Client::new();
+    let client = shared_blocking_client()?;
+    let request_id = new_request_id();
     let response = client
         .post(&api_url)
         .header("Authorization", format!("Bearer {}", access_token))
         .header("Content-Type", "application/json")
+        .header(REQUEST_ID_HEADER, &request_id)
         .json(&request_body)
         .send()
         .context("Failed to make API request")?;
 
-    if !response.status().is_success() {
+    let status = response.status();
+    if !status.is_success() {
+        let headers = response.headers().clone();
         let error_text = response
             .text()
             .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(anyhow::anyhow!("API request failed: {}", error_text));
+        return Err(api_error(status, &headers, error_text)
+            .with_request_id(&request_id)
+            .into());
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gcloud::{set_command_runner, MockCommandRunner};
+    use std::rc::Rc;
+
+    const AIPLATFORM_ENABLED: &str =
+        r#"[{"config": {"name": "aiplatform.googleapis.com"}, "state": "ENABLED"}]"#;
+
+    fn fast_options(check_billing: bool) -> ServiceOptions {
+        ServiceOptions {
+            check_billing,
+            activation_timeout: Duration::from_millis(50),
+            poll_interval: Duration::from_millis(1),
+            targeted_check: true,
+        }
+    }
+
+    #[test]
+    fn test_ensure_service_when_already_enabled() {
+        let runner = Rc::new(MockCommandRunner::new());
+        runner.push_output(AIPLATFORM_ENABLED);
+        let _guard = set_command_runner(runner.clone());
+
+        ensure_vertex_ai_service_with_options("my-project", &fast_options(false)).unwrap();
+        assert_eq!(
+            runner.calls(),
+            [
+                "gcloud services list --enabled --filter=config.name=aiplatform.googleapis.com \
+              --project my-project --format=json"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_targeted_check_falls_back_to_full_list() {
+        let runner = Rc::new(MockCommandRunner::new());
+        runner.push_failure("ERROR: (gcloud.services.list) unrecognized arguments: --enabled");
+        runner.push_output(AIPLATFORM_ENABLED);
+        let _guard = set_command_runner(runner.clone());
+
+        assert!(is_vertex_ai_enabled("my-project", true).unwrap());
+        assert_eq!(
+            runner.calls()[1],
+            "gcloud services list --project my-project --format=json"
+        );
+
+        // Other failures are reported rather than retried with the full list
+        runner.push_failure("ERROR: PERMISSION_DENIED");
+        assert!(is_vertex_ai_enabled("my-project", true).is_err());
+        assert_eq!(runner.calls().len(), 3);
+    }
+
+    #[test]
+    fn test_ensure_service_enables_a_disabled_service() {
+        let runner = Rc::new(MockCommandRunner::new());
+        runner.push_output(r#"[{"config": {"name": "storage.googleapis.com"}}]"#);
+        runner.push_output("");
+        runner.push_output("[]");
+        runner.push_output(AIPLATFORM_ENABLED);
+        let _guard = set_command_runner(runner.clone());
+
+        ensure_vertex_ai_service_with_options("my-project", &fast_options(false)).unwrap();
+        let calls = runner.calls();
+        assert_eq!(calls.len(), 4);
+        assert_eq!(
+            calls[1],
+            "gcloud services enable aiplatform.googleapis.com --project my-project"
+        );
+
+        // A failed enable is reported with gcloud's error
+        runner.push_output("[]");
+        runner.push_failure("ERROR: PERMISSION_DENIED");
+        let error =
+            ensure_vertex_ai_service_with_options("my-project", &fast_options(false)).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(VertexError::ServiceNotEnabled(message)) if message.contains("PERMISSION_DENIED")
+        ));
+    }
+
+    #[test]
+    fn test_ensure_service_requires_billing() {
+        let runner = Rc::new(MockCommandRunner::new());
+        runner.push_output(r#"{"billingEnabled": false}"#);
+        let _guard = set_command_runner(runner.clone());
+
+        let error =
+            ensure_vertex_ai_service_with_options("my-project", &fast_options(true)).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(VertexError::BillingDisabled(project)) if project == "my-project"
+        ));
+        assert_eq!(runner.calls().len(), 1);
+    }
+
+    #[test]
+    fn test_validate_region_suggests_nearest() {
+        let error = validate_region("europe-west-4").unwrap_err().to_string();
+        assert!(error.contains("Did you mean 'europe-west4'?"), "{}", error);
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn test_check_token_project() {
+        let error = check_token_project(Some("project-a".to_string()), "project-b")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("belongs to project project-a, but the configured project is project-b"),
+            "{}",
+            error
+        );
+        assert!(check_token_project(Some("project-b".to_string()), "project-b").is_ok());
+        assert!(check_token_project(None, "project-b").is_ok());
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn test_preflight_report_records_failures() {
+        let mut report = PreflightReport::default();
+        report.record(PreflightCheck::Region, Ok("us-central1".to_string()));
+        report.record(
+            PreflightCheck::Billing,
+            Err(VertexError::BillingDisabled("my-project".to_string()).into()),
+        );
+
+        assert!(!report.all_passed());
+        assert!(report.get(PreflightCheck::Region).unwrap().passed);
+        let failures: Vec<_> = report.failures().map(|result| result.check).collect();
+        assert_eq!(failures, vec![PreflightCheck::Billing]);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_model_id_from_name() {
+        assert_eq!(
+            model_id_from_name("publishers/google/models/gemini-2.0-flash"),
+            "gemini-2.0-flash"
+        );
+        assert_eq!(model_id_from_name("gemini-pro"), "gemini-pro");
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn test_locations_page_parsing() {
+        let page: LocationsPage = serde_json::from_str(
+            r#"{"locations": [{"name": "projects/p/locations/us-central1", "locationId": "us-central1"}],
+                "nextPageToken": "abc"}"#,
+        )
+        .unwrap();
+        assert_eq!(page.locations[0].location_id, "us-central1");
+        assert_eq!(page.next_page_token.as_deref(), Some("abc"));
+    }
+}
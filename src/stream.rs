@@ -0,0 +1,393 @@
+//! Streaming module for the Vertex AI Setup Tool
+//!
+//! This module decodes the server-sent events returned by the
+//! `streamGenerateContent?alt=sse` endpoint into typed response chunks.
+
+use anyhow::{Context, Result};
+use std::io::{self, Write};
+
+use crate::vertex_ai::{check_finish_reason, ApiErrorDetail, GenerateContentResponse, VertexError};
+
+/// Incremental decoder for `streamGenerateContent` server-sent events
+///
+/// Network reads can split an event anywhere, including inside a multi-byte
+/// UTF-8 character, so bytes are buffered until a complete line is available.
+#[derive(Debug, Default)]
+pub struct StreamDecoder {
+    /// Bytes received but not yet terminated by a newline
+    buffer: Vec<u8>,
+}
+
+impl StreamDecoder {
+    /// Creates a new, empty decoder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds bytes into the decoder and returns every chunk completed by them
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<GenerateContentResponse>> {
+        self.feed(bytes);
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = self.next_chunk()? {
+            chunks.push(chunk);
+        }
+
+        Ok(chunks)
+    }
+
+    /// Feeds bytes into the decoder without decoding them yet
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Decodes the next complete chunk, if any
+    ///
+    /// An error object in the stream is returned as
+    /// `VertexError::StreamInterrupted`, after every chunk that preceded it.
+    pub fn next_chunk(&mut self) -> Result<Option<GenerateContentResponse>> {
+        while let Some(newline) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=newline).collect();
+            if let Some(chunk) = parse_line(&line)? {
+                return Ok(Some(chunk));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Decodes any data left in the buffer once the stream has ended
+    pub fn finish(&mut self) -> Result<Option<GenerateContentResponse>> {
+        let line = std::mem::take(&mut self.buffer);
+        parse_line(&line)
+    }
+}
+
+/// Writes streamed text to a `Write` without splitting multi-byte characters
+///
+/// Bytes that end in the middle of a UTF-8 sequence are held back until the
+/// next chunk completes them, so the writer only ever receives valid UTF-8.
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::stream::StreamWriter;
+///
+/// let mut writer = StreamWriter::new(Vec::new(), true);
+/// let bytes = "é".as_bytes();
+/// writer.write_chunk(&bytes[..1])?;
+/// writer.write_chunk(&bytes[1..])?;
+/// assert_eq!(writer.finish()?, "é".as_bytes());
+/// Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct StreamWriter<W: Write> {
+    writer: W,
+    /// Trailing bytes of an incomplete UTF-8 sequence
+    pending: Vec<u8>,
+    flush_each_chunk: bool,
+}
+
+impl<W: Write> StreamWriter<W> {
+    /// Wraps a writer, optionally flushing it after every chunk
+    pub fn new(writer: W, flush_each_chunk: bool) -> Self {
+        Self {
+            writer,
+            pending: Vec::new(),
+            flush_each_chunk,
+        }
+    }
+
+    /// Writes every complete character in `bytes`, buffering an incomplete tail
+    pub fn write_chunk(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.pending.extend_from_slice(bytes);
+
+        let complete = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            // An incomplete sequence at the end may be finished by the next chunk
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        self.writer.write_all(&self.pending[..complete])?;
+        self.pending.drain(..complete);
+
+        if self.flush_each_chunk {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the writer and returns it, failing if a character was left incomplete
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.pending.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream ended inside a multi-byte character",
+            ));
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Parses a single SSE line, ignoring blank lines, comments, and non-data fields
+fn parse_line(line: &[u8]) -> Result<Option<GenerateContentResponse>> {
+    let line = std::str::from_utf8(line)
+        .map_err(|e| VertexError::Parse(e.to_string()))
+        .context("Stream contained invalid UTF-8")?
+        .trim();
+
+    let Some(data) = line.strip_prefix("data:") else {
+        return Ok(None);
+    };
+
+    let value: serde_json::Value = serde_json::from_str(data.trim())
+        .map_err(|e| VertexError::Parse(e.to_string()))
+        .context("Failed to parse stream chunk")?;
+
+    // Google can report a failure (e.g., exhausted quota) partway through a stream
+    if let Some(error) = value.get("error") {
+        let detail: ApiErrorDetail = serde_json::from_value(error.clone())
+            .map_err(|e| VertexError::Parse(e.to_string()))
+            .context("Failed to parse stream error")?;
+        return Err(VertexError::StreamInterrupted {
+            partial_text: String::new(),
+            code: detail.status,
+            message: detail.message,
+        }
+        .into());
+    }
+
+    let chunk = serde_json::from_value(value)
+        .map_err(|e| VertexError::Parse(e.to_string()))
+        .context("Failed to parse stream chunk")?;
+
+    Ok(Some(chunk))
+}
+
+/// Collects the text of a `streamGenerateContent` response as bytes arrive
+///
+/// If the stream reports an error partway through, the error is returned as
+/// `VertexError::StreamInterrupted` carrying every piece of text received
+/// before it, so the caller can keep the partial output.
+///
+/// # Example
+///
+/// ```rust
+/// use hvertex::stream::StreamTextCollector;
+///
+/// let mut collector = StreamTextCollector::new();
+/// collector.push(
+///     b"data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Hi\"}]}}]}\n",
+///     &mut |delta| {
+///         print!("{}", delta);
+///         Ok(())
+///     },
+/// )?;
+/// assert_eq!(collector.finish(&mut |_| Ok(()))?, "Hi");
+/// Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct StreamTextCollector {
+    decoder: StreamDecoder,
+    text: String,
+}
+
+impl StreamTextCollector {
+    /// Creates a new, empty collector
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the text received so far
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Feeds bytes into the collector, passing each new text delta to `on_delta`
+    pub fn push<F>(&mut self, bytes: &[u8], on_delta: &mut F) -> Result<()>
+    where
+        F: FnMut(&str) -> Result<()>,
+    {
+        self.decoder.feed(bytes);
+        loop {
+            match self.decoder.next_chunk() {
+                Ok(Some(chunk)) => self.handle_chunk(chunk, on_delta)?,
+                Ok(None) => return Ok(()),
+                Err(e) => return Err(self.with_partial_text(e)),
+            }
+        }
+    }
+
+    /// Decodes any data left once the stream has ended and returns the full text
+    pub fn finish<F>(mut self, on_delta: &mut F) -> Result<String>
+    where
+        F: FnMut(&str) -> Result<()>,
+    {
+        match self.decoder.finish() {
+            Ok(Some(chunk)) => self.handle_chunk(chunk, on_delta)?,
+            Ok(None) => {}
+            Err(e) => return Err(self.with_partial_text(e)),
+        }
+        Ok(self.text)
+    }
+
+    /// Appends a chunk's text and reports it as a delta
+    ///
+    /// Chunks without candidates (e.g., a final usage-only chunk) are skipped
+    /// unless the prompt was blocked. A candidate that stopped for any other
+    /// reason than "STOP" fails with `VertexError::StreamInterrupted`, keeping
+    /// the text received so far.
+    fn handle_chunk<F>(&mut self, chunk: GenerateContentResponse, on_delta: &mut F) -> Result<()>
+    where
+        F: FnMut(&str) -> Result<()>,
+    {
+        let Some(candidate) = chunk.candidates.first() else {
+            let prompt_blocked = chunk
+                .prompt_feedback
+                .as_ref()
+                .is_some_and(|feedback| feedback.block_reason.is_some());
+            return if prompt_blocked {
+                chunk.text().map(|_| ())
+            } else {
+                Ok(())
+            };
+        };
+
+        let delta = candidate.text();
+        if !delta.is_empty() {
+            on_delta(&delta)?;
+            self.text.push_str(&delta);
+        }
+
+        check_finish_reason(candidate).map_err(|e| {
+            VertexError::StreamInterrupted {
+                partial_text: self.text.clone(),
+                code: candidate.finish_reason.clone(),
+                message: e.to_string(),
+            }
+            .into()
+        })
+    }
+
+    /// Attaches the text received so far to a mid-stream error
+    fn with_partial_text(&self, error: anyhow::Error) -> anyhow::Error {
+        match error.downcast::<VertexError>() {
+            Ok(VertexError::StreamInterrupted { code, message, .. }) => {
+                VertexError::StreamInterrupted {
+                    partial_text: self.text.clone(),
+                    code,
+                    message,
+                }
+                .into()
+            }
+            Ok(other) => other.into(),
+            Err(error) => error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder_buffers_split_events() {
+        let event =
+            "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"héllo\"}]}}]}\r\n\r\n";
+        let bytes = event.as_bytes();
+        let mut decoder = StreamDecoder::new();
+
+        // Split inside the multi-byte "é"
+        let split = event.find('é').unwrap() + 1;
+        assert!(decoder.push(&bytes[..split]).unwrap().is_empty());
+
+        let chunks = decoder.push(&bytes[split..]).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].candidates[0].text(), "héllo");
+        assert!(decoder.finish().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_collector_keeps_text_before_error_chunk() {
+        let stream = concat!(
+            "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"{\\\"a\\\": \"}]}}]}\n\n",
+            "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"1,\"}]}}]}\n\n",
+            "data: {\"error\":{\"code\":429,\"message\":\"Quota exceeded\",\"status\":\"RESOURCE_EXHAUSTED\"}}\n\n",
+        );
+
+        let mut deltas = Vec::new();
+        let mut collector = StreamTextCollector::new();
+        let error = collector
+            .push(stream.as_bytes(), &mut |delta| {
+                deltas.push(delta.to_string());
+                Ok(())
+            })
+            .unwrap_err();
+
+        assert_eq!(deltas.len(), 2);
+        match error.downcast_ref::<VertexError>() {
+            Some(VertexError::StreamInterrupted {
+                partial_text,
+                code,
+                message,
+            }) => {
+                assert_eq!(partial_text, "{\"a\": 1,");
+                assert_eq!(code.as_deref(), Some("RESOURCE_EXHAUSTED"));
+                assert_eq!(message, "Quota exceeded");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_collector_keeps_text_when_generation_stops_early() {
+        let stream = concat!(
+            "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"{\\\"a\\\": \"}]}}]}\n\n",
+            "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"1,\"}]},\"finishReason\":\"MAX_TOKENS\"}]}\n\n",
+            "data: {\"usageMetadata\":{\"totalTokenCount\":12}}\n\n",
+        );
+
+        let mut collector = StreamTextCollector::new();
+        let error = collector
+            .push(stream.as_bytes(), &mut |_| Ok(()))
+            .unwrap_err();
+        match error.downcast_ref::<VertexError>() {
+            Some(VertexError::StreamInterrupted {
+                partial_text, code, ..
+            }) => {
+                assert_eq!(partial_text, "{\"a\": 1,");
+                assert_eq!(code.as_deref(), Some("MAX_TOKENS"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // A usage-only chunk after normal output is skipped
+        let stream = concat!(
+            "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"ok\"}]},\"finishReason\":\"STOP\"}]}\n\n",
+            "data: {\"usageMetadata\":{\"totalTokenCount\":3}}\n\n",
+        );
+        let mut collector = StreamTextCollector::new();
+        collector.push(stream.as_bytes(), &mut |_| Ok(())).unwrap();
+        assert_eq!(collector.finish(&mut |_| Ok(())).unwrap(), "ok");
+    }
+
+    #[test]
+    fn test_writer_holds_back_split_characters() {
+        let text = "naïve 日本";
+        let bytes = text.as_bytes();
+        let split = text.find('日').unwrap() + 1;
+
+        let mut writer = StreamWriter::new(Vec::new(), true);
+        writer.write_chunk(&bytes[..split]).unwrap();
+        assert!(std::str::from_utf8(&writer.writer).is_ok());
+        writer.write_chunk(&bytes[split..]).unwrap();
+
+        let output = writer.finish().unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), text);
+
+        let mut writer = StreamWriter::new(Vec::new(), false);
+        writer.write_chunk(&bytes[..split]).unwrap();
+        assert!(writer.finish().is_err());
+    }
+}
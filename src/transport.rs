@@ -0,0 +1,363 @@
+//! Transport module for the Vertex AI Setup Tool
+//!
+//! This module abstracts how request bodies reach the Vertex AI API, so the
+//! request-building and response-parsing logic can be exercised without
+//! credentials or network access.
+
+use anyhow::Result;
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+use std::future::Future;
+use std::time::Duration;
+use tracing::{debug, enabled, Level};
+
+use crate::auth::TokenProvider;
+use crate::redact::{redact_request, redact_response};
+use crate::vertex_ai::{
+    build_http_client, error_for_status, request_headers, send_with_retry, transport_error,
+    ModelEndpoint, RetryPolicy,
+};
+
+/// Sends requests to Vertex AI URLs and returns the JSON responses
+///
+/// Implementations report API failures as `VertexError` values carried inside
+/// `anyhow::Error`, like the rest of the crate.
+pub trait Transport {
+    /// Sends `body` to a method URL (e.g., `generateContent` or `predict`) and
+    /// returns the response body
+    fn generate_content(
+        &self,
+        url: &str,
+        body: &Value,
+    ) -> impl Future<Output = Result<Value>> + Send;
+
+    /// Sends a GET request to a Vertex AI URL and returns the response body
+    ///
+    /// Used for lookups such as which regions serve a model. The default fails,
+    /// for transports that only send generation requests.
+    fn get(&self, url: &str) -> impl Future<Output = Result<Value>> + Send {
+        let url = url.to_string();
+        async move { Err(anyhow::anyhow!("This transport cannot send GET {}", url)) }
+    }
+
+    /// Discards any cached credentials so the next request fetches a new token
+    ///
+    /// Called once after a request fails with `VertexError::Unauthorized`. The
+    /// default does nothing, for transports that do not cache credentials.
+    fn refresh_token(&self) -> impl Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+}
+
+/// The default transport, which sends requests over HTTPS with `reqwest`
+///
+/// The access token comes from the process-wide [`TokenProvider`] for the
+/// configured identity, so concurrent transports share one gcloud fetch and
+/// reuse the token until the API rejects it. Transient failures are retried.
+///
+/// A new `reqwest::Client` is built for each request unless one is supplied
+/// with [`HttpTransport::with_client`].
+///
+/// Request and response bodies are logged at the `DEBUG` level with inline
+/// data and personal data masked. Clear `redact` to log them unchanged.
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    /// Request timeout (defaults to 120 seconds)
+    pub timeout: Option<Duration>,
+    /// Service account to impersonate when fetching the access token
+    pub impersonate_service_account: Option<String>,
+    /// Project billed for quota, sent as the `X-Goog-User-Project` header
+    pub quota_project: Option<String>,
+    /// Additional headers sent with every request
+    pub extra_headers: Vec<(String, String)>,
+    /// Whether logged request and response bodies are redacted
+    /// (see [`crate::redact`]; on unless `VERTEX_AI_REDACT_LOGS=0`)
+    pub redact: bool,
+    /// A client reused for every request, so connections are pooled
+    client: Option<reqwest::Client>,
+    /// A token provider used instead of the process-wide one
+    tokens: Option<TokenProvider>,
+}
+
+impl Default for HttpTransport {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            impersonate_service_account: None,
+            quota_project: None,
+            extra_headers: Vec::new(),
+            redact: redact_from_env(),
+            client: None,
+            tokens: None,
+        }
+    }
+}
+
+impl HttpTransport {
+    /// Creates a transport that authenticates the same way as `endpoint`
+    pub fn for_endpoint(endpoint: &ModelEndpoint, timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            impersonate_service_account: endpoint.impersonate_service_account.clone(),
+            quota_project: endpoint.quota_project.clone(),
+            extra_headers: endpoint.extra_headers.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// Sends every request with `client` instead of building a new one
+    ///
+    /// The client's own timeout applies; `timeout` is only used in error messages.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Fetches access tokens from `tokens` instead of the process-wide provider
+    pub fn with_token_provider(mut self, tokens: TokenProvider) -> Self {
+        self.tokens = Some(tokens);
+        self
+    }
+
+    /// Sets whether logged request and response bodies are redacted
+    pub fn with_redaction(mut self, redact: bool) -> Self {
+        self.redact = redact;
+        self
+    }
+
+    /// Returns the token provider for the configured identity
+    fn token_provider(&self) -> TokenProvider {
+        self.tokens
+            .clone()
+            .unwrap_or_else(|| TokenProvider::shared(self.impersonate_service_account.as_deref()))
+    }
+
+    /// Returns the client to send a request with
+    pub(crate) fn client(&self) -> Result<reqwest::Client> {
+        match &self.client {
+            Some(client) => Ok(client.clone()),
+            None => build_http_client(self.timeout),
+        }
+    }
+
+    /// Returns the form of a request body written to the debug log
+    fn logged_request(&self, body: &Value) -> Value {
+        if self.redact {
+            redact_request(body)
+        } else {
+            body.clone()
+        }
+    }
+
+    /// Returns the form of a response body written to the debug log
+    fn logged_response(&self, body: &Value) -> Value {
+        if self.redact {
+            redact_response(body)
+        } else {
+            body.clone()
+        }
+    }
+
+    /// Builds the headers for a request, fetching an access token if needed
+    pub(crate) async fn headers(&self) -> Result<HeaderMap> {
+        let access_token = self.token_provider().token().await?;
+        request_headers(
+            &access_token,
+            self.quota_project.as_deref(),
+            &self.extra_headers,
+        )
+    }
+}
+
+/// Reads whether to redact logged bodies from `VERTEX_AI_REDACT_LOGS`
+///
+/// Redaction is on unless the variable is set to `0`, `false`, or `no`.
+fn redact_from_env() -> bool {
+    !std::env::var(crate::config::env::REDACT_LOGS)
+        .is_ok_and(|value| matches!(value.as_str(), "0" | "false" | "FALSE" | "no"))
+}
+
+impl Transport for HttpTransport {
+    async fn generate_content(&self, url: &str, body: &Value) -> Result<Value> {
+        // Set up the HTTP client and request headers
+        let client = self.client()?;
+        let headers = self.headers().await?;
+
+        if enabled!(Level::DEBUG) {
+            let logged = self.logged_request(body);
+            debug!(url, body = %logged, "Sending Vertex AI request");
+        }
+
+        // Make the API request, retrying transient failures
+        let response = send_with_retry(
+            &client,
+            |client| client.post(url).headers(headers.clone()).json(body),
+            RetryPolicy::default(),
+        )
+        .await
+        .map_err(|e| transport_error(e, self.timeout, "Failed to make Vertex AI API request"))?;
+
+        // Check if the request was successful
+        let response = error_for_status(response).await?;
+
+        // Parse the response
+        let response: Value = response.json().await.map_err(|e| {
+            transport_error(e, self.timeout, "Failed to parse API response as JSON")
+        })?;
+
+        if enabled!(Level::DEBUG) {
+            let logged = self.logged_response(&response);
+            debug!(url, body = %logged, "Received Vertex AI response");
+        }
+        Ok(response)
+    }
+
+    async fn get(&self, url: &str) -> Result<Value> {
+        let client = self.client()?;
+        let headers = self.headers().await?;
+
+        let response = client.get(url).headers(headers).send().await.map_err(|e| {
+            transport_error(e, self.timeout, "Failed to make Vertex AI API request")
+        })?;
+        let response = error_for_status(response).await?;
+        response
+            .json()
+            .await
+            .map_err(|e| transport_error(e, self.timeout, "Failed to parse API response as JSON"))
+    }
+
+    async fn refresh_token(&self) -> Result<()> {
+        let provider = self.token_provider();
+        provider.invalidate().await;
+        provider.token().await.map(|_| ())
+    }
+}
+
+/// A transport that returns canned responses, for tests that must not reach Google
+///
+/// Responses are returned in the order they were pushed, and every request is
+/// recorded so tests can inspect the URL and body that would have been sent.
+/// Available in this crate's tests and with the `testing` feature.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use hvertex::transport::MockTransport;
+/// use serde_json::json;
+///
+/// let transport = MockTransport::new();
+/// transport.push_response(json!({
+///     "candidates": [{ "content": { "parts": [{ "text": "{}" }] }, "finishReason": "STOP" }]
+/// }));
+/// ```
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    responses: std::sync::Mutex<std::collections::VecDeque<Result<Value>>>,
+    requests: std::sync::Mutex<Vec<(String, Value)>>,
+    refreshes: std::sync::atomic::AtomicUsize,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl MockTransport {
+    /// Creates a transport with no queued responses
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a successful response body
+    pub fn push_response(&self, response: Value) {
+        self.push_result(Ok(response));
+    }
+
+    /// Queues an error, such as a `VertexError`
+    pub fn push_error(&self, error: impl Into<anyhow::Error>) {
+        self.push_result(Err(error.into()));
+    }
+
+    /// Returns every request sent so far as `(url, body)` pairs; GET requests
+    /// have a `null` body
+    pub fn requests(&self) -> Vec<(String, Value)> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Returns how many times `refresh_token` has been called
+    pub fn refresh_count(&self) -> usize {
+        self.refreshes.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    fn next_response(&self) -> Result<Value> {
+        self.responses
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .pop_front()
+            .unwrap_or_else(|| Err(anyhow::anyhow!("MockTransport has no response queued")))
+    }
+
+    fn push_result(&self, result: Result<Value>) {
+        self.responses
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push_back(result);
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Transport for MockTransport {
+    async fn generate_content(&self, url: &str, body: &Value) -> Result<Value> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push((url.to_string(), body.clone()));
+
+        self.next_response()
+    }
+
+    /// Records the request with a `null` body and returns the next queued response
+    async fn get(&self, url: &str) -> Result<Value> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push((url.to_string(), Value::Null));
+
+        self.next_response()
+    }
+
+    async fn refresh_token(&self) -> Result<()> {
+        self.refreshes
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_default_transport_logs_redacted_bodies() {
+        let transport = HttpTransport::default();
+        assert!(transport.redact);
+
+        let request = json!({
+            "contents": [{ "role": "user", "parts": [
+                { "inlineData": { "mimeType": "application/pdf", "data": "JVBERi0xLjQK" } }
+            ] }]
+        });
+        let logged = transport.logged_request(&request).to_string();
+        assert!(!logged.contains("JVBERi0xLjQK"));
+
+        let response = json!({
+            "candidates": [{ "content": { "parts": [{ "text": "{\"email\": \"jane.doe@example.com\"}" }] } }]
+        });
+        let logged = transport.logged_response(&response).to_string();
+        assert!(!logged.contains("jane.doe"));
+
+        let transport = transport.with_redaction(false);
+        assert_eq!(transport.logged_request(&request), request);
+    }
+}